@@ -3,7 +3,11 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use cosmos_atomic_swap::{ExecuteMsg, InstantiateMsg, TransferMsg};
+use cosmos_atomic_swap::ibc::{SwapAck, SwapPacket};
+use cosmos_atomic_swap::{
+    BatchItemResult, ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, ReceiveMsg, SudoMsg,
+    TransferMsg,
+};
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -14,4 +18,11 @@ fn main() {
     export_schema(&schema_for!(TransferMsg), &out_dir);
     export_schema(&schema_for!(InstantiateMsg), &out_dir);
     export_schema(&schema_for!(ExecuteMsg), &out_dir);
+    export_schema(&schema_for!(ReceiveMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
+    export_schema(&schema_for!(SudoMsg), &out_dir);
+    export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(BatchItemResult), &out_dir);
+    export_schema(&schema_for!(SwapPacket), &out_dir);
+    export_schema(&schema_for!(SwapAck), &out_dir);
 }
@@ -0,0 +1,160 @@
+//! Batches of fund, confirm and refund messages.
+//!
+//! A batch either fails as a whole, or in best-effort mode skips the failing items. Either way
+//! the data of the response reports a `BatchItemResult` per item and the payouts of the batch
+//! are summed per recipient and denom.
+use cosmwasm_std::{to_json_binary, Addr, BankMsg, DepsMut, Env, MessageInfo, Response};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utils::SecretKey;
+
+use crate::ibc::relay_secret;
+use crate::{
+    assert_not_paused, config, confirm_transfer, deduct_payment, excess_payment, fund_transfer,
+    hash32, load_pause_state, refund_transfer, Asset, ContractError, Hash32, Payouts, TransferId,
+    TransferMsg,
+};
+
+/// The outcome of a single item of a batch
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BatchItemResult {
+    #[serde(with = "hash32")]
+    #[schemars(with = "Hash32")]
+    pub id: TransferId,
+    /// the error skipping the item in best-effort mode
+    pub error: Option<String>,
+}
+
+/// collects the outcome of an item, failing the batch unless it is best-effort
+fn record_item<T>(
+    results: &mut Vec<BatchItemResult>,
+    id: TransferId,
+    item: Result<T, ContractError>,
+    best_effort: bool,
+) -> Result<Option<T>, ContractError> {
+    match item {
+        Ok(item) => {
+            results.push(BatchItemResult { id, error: None });
+            Ok(Some(item))
+        }
+        Err(err) if best_effort => {
+            results.push(BatchItemResult {
+                id,
+                error: Some(err.to_string()),
+            });
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn batch_response(action: &str, results: &[BatchItemResult]) -> Result<Response, ContractError> {
+    let succeeded = results.iter().filter(|item| item.error.is_none()).count();
+    Ok(Response::new()
+        .add_attribute("action", action)
+        .add_attribute("succeeded", succeeded.to_string())
+        .add_attribute("failed", (results.len() - succeeded).to_string())
+        .set_data(to_json_binary(results)?))
+}
+
+pub fn batch_fund(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    transfers: Vec<TransferMsg>,
+    best_effort: bool,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, |state| state.fund, "fund")?;
+    let config_state = config(deps.storage).load()?;
+    // every transfer is paid from what the earlier ones left of the funds
+    let mut remaining = info.funds;
+    let mut results = vec![];
+    for msg in transfers {
+        let id = msg.transfer_id();
+        let item = fund_transfer(
+            deps.branch(),
+            &env,
+            &config_state,
+            &remaining,
+            Asset::Native,
+            msg,
+            None,
+        );
+        if let Some((_, record)) = record_item(&mut results, id, item, best_effort)? {
+            deduct_payment(&mut remaining, &record);
+        }
+    }
+
+    let mut res = batch_response("batch_fund", &results)?;
+    // the deposits of skipped transfers go back to the caller, so only atomic batches can
+    // reject an inexact payment
+    if best_effort {
+        remaining.retain(|coin| !coin.amount.is_zero());
+    } else {
+        remaining = excess_payment(&config_state, remaining)?;
+    }
+    if !remaining.is_empty() {
+        res = res.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: remaining,
+        });
+    }
+    Ok(res)
+}
+
+pub fn batch_confirm(
+    deps: DepsMut,
+    env: Env,
+    caller: Addr,
+    items: Vec<(TransferId, SecretKey)>,
+    best_effort: bool,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, |state| state.confirm, "confirm")?;
+    let config_state = config(deps.storage).load()?;
+    let mut payouts = Payouts::default();
+    let mut relays = vec![];
+    let mut results = vec![];
+    for (id, secret) in items {
+        let item = confirm_transfer(
+            deps.storage,
+            &env,
+            &config_state,
+            &caller,
+            id,
+            secret,
+            &mut payouts,
+        );
+        if let Some(transfer) = record_item(&mut results, id, item, best_effort)? {
+            relays.extend(relay_secret(&transfer)?);
+        }
+    }
+    Ok(batch_response("batch_confirm", &results)?
+        .add_submessages(payouts.into_msgs()?)
+        .add_messages(relays))
+}
+
+pub fn batch_refund(
+    deps: DepsMut,
+    env: Env,
+    caller: Addr,
+    ids: Vec<TransferId>,
+    best_effort: bool,
+) -> Result<Response, ContractError> {
+    let paused = load_pause_state(deps.storage)?.refund;
+    let config_state = config(deps.storage).load()?;
+    let mut payouts = Payouts::default();
+    let mut results = vec![];
+    for id in ids {
+        let item = refund_transfer(
+            deps.storage,
+            &env,
+            &config_state,
+            paused,
+            &caller,
+            id,
+            &mut payouts,
+        );
+        record_item(&mut results, id, item, best_effort)?;
+    }
+    Ok(batch_response("batch_refund", &results)?.add_submessages(payouts.into_msgs()?))
+}
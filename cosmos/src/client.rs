@@ -0,0 +1,538 @@
+//! A typed client for the contract, enabled by the `client` feature.
+//!
+//! The client builds the contract messages, signs them as `MsgExecuteContract` transactions in
+//! `SIGN_MODE_DIRECT` and hands them to a `Transport`, so the RPC layer can be replaced by a
+//! local mock in tests.
+use cosmwasm_std::{from_json, to_json_vec, Coin, StdError};
+use prost::Message;
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+use utils::{gen_lock, HashLock, SecretKey};
+
+use crate::{
+    Config, CrossChainLeg, ExecuteMsg, FeeRecipient, FeeRule, Forward, InstantiateMsg,
+    PremiumTerms, QueryMsg, TimelockKind, TransferId, TransferMsg, TransferRecord,
+};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ClientError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("Transport error: {0}")]
+    Transport(String),
+
+    #[error("Signing error: {0}")]
+    Signing(String),
+
+    #[error("Transaction failed with code {code}: {log}")]
+    TxFailed { code: u32, log: String },
+}
+
+/// The account number and sequence a transaction is signed with
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Account {
+    pub account_number: u64,
+    pub sequence: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TxResponse {
+    pub txhash: String,
+    pub code: u32,
+    pub raw_log: String,
+    /// the data set by the contract, the transfer id for `Fund`
+    pub data: Vec<u8>,
+}
+
+/// The RPC layer of a chain
+pub trait Transport {
+    fn account(&self, address: &str) -> Result<Account, ClientError>;
+    /// runs a smart query, `query` and the result are JSON
+    fn query_smart(&self, contract: &str, query: &[u8]) -> Result<Vec<u8>, ClientError>;
+    /// broadcasts an encoded `TxRaw`
+    fn broadcast(&self, tx: &[u8]) -> Result<TxResponse, ClientError>;
+}
+
+/// A secp256k1 key signing transactions
+pub trait Signer {
+    fn address(&self) -> String;
+    /// the compressed public key
+    fn public_key(&self) -> Vec<u8>;
+    /// signs the encoded `SignDoc`
+    fn sign(&self, sign_doc: &[u8]) -> Result<Vec<u8>, ClientError>;
+}
+
+/// The protobuf messages of a signed transaction
+pub mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Any {
+        #[prost(string, tag = "1")]
+        pub type_url: String,
+        #[prost(bytes = "vec", tag = "2")]
+        pub value: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Coin {
+        #[prost(string, tag = "1")]
+        pub denom: String,
+        #[prost(string, tag = "2")]
+        pub amount: String,
+    }
+
+    /// `cosmwasm.wasm.v1.MsgExecuteContract`
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MsgExecuteContract {
+        #[prost(string, tag = "1")]
+        pub sender: String,
+        #[prost(string, tag = "2")]
+        pub contract: String,
+        #[prost(bytes = "vec", tag = "3")]
+        pub msg: Vec<u8>,
+        #[prost(message, repeated, tag = "5")]
+        pub funds: Vec<Coin>,
+    }
+
+    /// `cosmwasm.wasm.v1.MsgInstantiateContract`
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MsgInstantiateContract {
+        #[prost(string, tag = "1")]
+        pub sender: String,
+        #[prost(string, tag = "2")]
+        pub admin: String,
+        #[prost(uint64, tag = "3")]
+        pub code_id: u64,
+        #[prost(string, tag = "4")]
+        pub label: String,
+        #[prost(bytes = "vec", tag = "5")]
+        pub msg: Vec<u8>,
+        #[prost(message, repeated, tag = "6")]
+        pub funds: Vec<Coin>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TxBody {
+        #[prost(message, repeated, tag = "1")]
+        pub messages: Vec<Any>,
+        #[prost(string, tag = "2")]
+        pub memo: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PubKey {
+        #[prost(bytes = "vec", tag = "1")]
+        pub key: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ModeInfoSingle {
+        #[prost(int32, tag = "1")]
+        pub mode: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ModeInfo {
+        #[prost(message, optional, tag = "1")]
+        pub single: Option<ModeInfoSingle>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SignerInfo {
+        #[prost(message, optional, tag = "1")]
+        pub public_key: Option<Any>,
+        #[prost(message, optional, tag = "2")]
+        pub mode_info: Option<ModeInfo>,
+        #[prost(uint64, tag = "3")]
+        pub sequence: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Fee {
+        #[prost(message, repeated, tag = "1")]
+        pub amount: Vec<Coin>,
+        #[prost(uint64, tag = "2")]
+        pub gas_limit: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct AuthInfo {
+        #[prost(message, repeated, tag = "1")]
+        pub signer_infos: Vec<SignerInfo>,
+        #[prost(message, optional, tag = "2")]
+        pub fee: Option<Fee>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SignDoc {
+        #[prost(bytes = "vec", tag = "1")]
+        pub body_bytes: Vec<u8>,
+        #[prost(bytes = "vec", tag = "2")]
+        pub auth_info_bytes: Vec<u8>,
+        #[prost(string, tag = "3")]
+        pub chain_id: String,
+        #[prost(uint64, tag = "4")]
+        pub account_number: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TxRaw {
+        #[prost(bytes = "vec", tag = "1")]
+        pub body_bytes: Vec<u8>,
+        #[prost(bytes = "vec", tag = "2")]
+        pub auth_info_bytes: Vec<u8>,
+        #[prost(bytes = "vec", repeated, tag = "3")]
+        pub signatures: Vec<Vec<u8>>,
+    }
+
+    impl From<&cosmwasm_std::Coin> for Coin {
+        fn from(coin: &cosmwasm_std::Coin) -> Self {
+            Coin {
+                denom: coin.denom.clone(),
+                amount: coin.amount.to_string(),
+            }
+        }
+    }
+}
+
+pub const MSG_EXECUTE_CONTRACT: &str = "/cosmwasm.wasm.v1.MsgExecuteContract";
+pub const MSG_INSTANTIATE_CONTRACT: &str = "/cosmwasm.wasm.v1.MsgInstantiateContract";
+const SECP256K1_PUB_KEY: &str = "/cosmos.crypto.secp256k1.PubKey";
+const SIGN_MODE_DIRECT: i32 = 1;
+
+/// Builds a `TransferMsg`, the hashlock is taken from the secret or given as is
+#[derive(Clone, Debug, Default)]
+pub struct TransferBuilder {
+    msg: TransferMsg,
+}
+
+impl TransferBuilder {
+    pub fn new(sender: impl Into<String>, receiver: impl Into<String>, coin: Coin) -> Self {
+        Self {
+            msg: TransferMsg {
+                sender: sender.into(),
+                receiver: receiver.into(),
+                coin,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn secret(self, secret: SecretKey) -> Self {
+        self.hashlock(gen_lock(secret))
+    }
+
+    pub fn hashlock(mut self, hashlock: HashLock) -> Self {
+        self.msg.hashlock = hashlock;
+        self
+    }
+
+    /// locks until the unix timestamp, in seconds
+    pub fn timelock(mut self, timelock: u64) -> Self {
+        self.msg.timelock = timelock;
+        self.msg.timelock_kind = TimelockKind::Timestamp;
+        self
+    }
+
+    /// locks until the block height
+    pub fn height_lock(mut self, height: u64) -> Self {
+        self.msg.timelock = height;
+        self.msg.timelock_kind = TimelockKind::Height;
+        self
+    }
+
+    pub fn tip(mut self, tip: Coin) -> Self {
+        self.msg.tip = Some(tip);
+        self
+    }
+
+    pub fn referrer(mut self, referrer: impl Into<String>) -> Self {
+        self.msg.referrer = Some(referrer.into());
+        self
+    }
+
+    /// only the receiver or `delegate` can confirm during the first `window` seconds, the
+    /// delegate becomes part of the id
+    pub fn exclusive_window(mut self, window: u64, delegate: Option<String>) -> Self {
+        self.msg.exclusive_window = Some(window);
+        self.msg.delegate = delegate;
+        self
+    }
+
+    /// pays the receiver on another chain over ICS-20, the forward becomes part of the id
+    pub fn forward(mut self, forward: Forward) -> Self {
+        self.msg.forward = Some(forward);
+        self
+    }
+
+    /// asks the counterparty for a premium due `deposit_window` seconds after funding
+    pub fn premium(mut self, coin: Coin, deposit_window: u64) -> Self {
+        self.msg.premium = Some(PremiumTerms {
+            coin,
+            deposit_window,
+        });
+        self
+    }
+
+    /// locks another leg of a swap with a hashlock already used on this contract
+    pub fn allow_hashlock_reuse(mut self) -> Self {
+        self.msg.allow_hashlock_reuse = true;
+        self
+    }
+
+    pub fn build(self) -> TransferMsg {
+        self.msg
+    }
+
+    pub fn fund(self) -> ExecuteMsg {
+        ExecuteMsg::Fund(self.msg)
+    }
+
+    /// funds the transfer as one leg of a cross-chain swap
+    pub fn fund_leg(self, leg: CrossChainLeg) -> ExecuteMsg {
+        match leg {
+            CrossChainLeg::Out(leg) => ExecuteMsg::TransferOut {
+                transfer: self.msg,
+                leg,
+            },
+            CrossChainLeg::In(leg) => ExecuteMsg::TransferIn {
+                transfer: self.msg,
+                leg,
+            },
+        }
+    }
+}
+
+/// Builds an `InstantiateMsg`, the bounds left unset take the contract defaults
+#[derive(Clone, Debug, Default)]
+pub struct InstantiateBuilder {
+    msg: InstantiateMsg,
+}
+
+impl InstantiateBuilder {
+    pub fn new(platform: impl Into<String>) -> Self {
+        Self {
+            msg: InstantiateMsg {
+                platform: platform.into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn fee(mut self, rule: FeeRule) -> Self {
+        self.msg.fees.push(rule);
+        self
+    }
+
+    pub fn refund_fee(mut self, refund_fee: bool) -> Self {
+        self.msg.refund_fee = refund_fee;
+        self
+    }
+
+    /// the lock duration bounds, in seconds
+    pub fn timelock_bounds(mut self, min: u64, max: u64) -> Self {
+        self.msg.min_timelock = Some(min);
+        self.msg.max_timelock = Some(max);
+        self
+    }
+
+    /// the lock duration bounds, in blocks
+    pub fn height_lock_bounds(mut self, min: u64, max: u64) -> Self {
+        self.msg.min_height_lock = Some(min);
+        self.msg.max_height_lock = Some(max);
+        self
+    }
+
+    pub fn cleanup(mut self, retention: u64, bounty_bps: u16) -> Self {
+        self.msg.cleanup_retention = Some(retention);
+        self.msg.cleanup_bounty_bps = Some(bounty_bps);
+        self
+    }
+
+    pub fn strict_payment(mut self, strict_payment: bool) -> Self {
+        self.msg.strict_payment = strict_payment;
+        self
+    }
+
+    pub fn restrict_assets(mut self, restrict_assets: bool) -> Self {
+        self.msg.restrict_assets = restrict_assets;
+        self
+    }
+
+    pub fn fee_recipient(mut self, address: impl Into<String>, weight: u32) -> Self {
+        self.msg.fee_recipients.push(FeeRecipient {
+            address: address.into(),
+            weight,
+        });
+        self
+    }
+
+    /// the share of the platform fee paid to referrers, in bps
+    pub fn referrer_share(mut self, bps: u16) -> Self {
+        self.msg.referrer_share_bps = Some(bps);
+        self
+    }
+
+    pub fn build(self) -> InstantiateMsg {
+        self.msg
+    }
+}
+
+/// A client of a deployed contract, signing as `signer`
+pub struct Client<T, S> {
+    pub transport: T,
+    pub signer: S,
+    pub chain_id: String,
+    pub contract: String,
+    /// the transaction fee, in the fee denom of the chain
+    pub fee: Vec<Coin>,
+    pub gas_limit: u64,
+}
+
+impl<T: Transport, S: Signer> Client<T, S> {
+    pub fn new(
+        transport: T,
+        signer: S,
+        chain_id: impl Into<String>,
+        contract: impl Into<String>,
+    ) -> Self {
+        Self {
+            transport,
+            signer,
+            chain_id: chain_id.into(),
+            contract: contract.into(),
+            fee: vec![],
+            gas_limit: 200_000,
+        }
+    }
+
+    pub fn with_fee(mut self, fee: Vec<Coin>, gas_limit: u64) -> Self {
+        self.fee = fee;
+        self.gas_limit = gas_limit;
+        self
+    }
+
+    pub fn query<R: DeserializeOwned>(&self, msg: &QueryMsg) -> Result<R, ClientError> {
+        let res = self
+            .transport
+            .query_smart(&self.contract, &to_json_vec(msg)?)?;
+        Ok(from_json(res)?)
+    }
+
+    pub fn transfer(&self, id: TransferId) -> Result<TransferRecord, ClientError> {
+        self.query(&QueryMsg::TransferById { id })
+    }
+
+    pub fn config(&self) -> Result<Config, ClientError> {
+        self.query(&QueryMsg::Config {})
+    }
+
+    /// signs and broadcasts `msg` with `funds`
+    pub fn execute(&self, msg: &ExecuteMsg, funds: &[Coin]) -> Result<TxResponse, ClientError> {
+        let msg = proto::MsgExecuteContract {
+            sender: self.signer.address(),
+            contract: self.contract.clone(),
+            msg: to_json_vec(msg)?,
+            funds: funds.iter().map(Into::into).collect(),
+        };
+        self.broadcast(MSG_EXECUTE_CONTRACT, msg.encode_to_vec())
+    }
+
+    /// funds `msg` with its coin, fee and tip, returns the transfer id
+    pub fn fund(&self, msg: TransferMsg) -> Result<TransferId, ClientError> {
+        let config = self.config()?;
+        let mut funds = vec![msg.coin.clone()];
+        for coin in config.fee_for(&msg.coin).into_iter().chain(msg.tip.clone()) {
+            match funds.iter_mut().find(|fund| fund.denom == coin.denom) {
+                Some(fund) => fund.amount += coin.amount,
+                None => funds.push(coin),
+            }
+        }
+        let res = self.execute(&ExecuteMsg::Fund(msg), &funds)?;
+        res.data
+            .try_into()
+            .map_err(|_| ClientError::Transport("the fund data is not a transfer id".into()))
+    }
+
+    pub fn confirm(&self, id: TransferId, secret: SecretKey) -> Result<TxResponse, ClientError> {
+        self.execute(&ExecuteMsg::ConfirmById { id, secret }, &[])
+    }
+
+    pub fn refund(&self, id: TransferId) -> Result<TxResponse, ClientError> {
+        self.execute(&ExecuteMsg::RefundById { id }, &[])
+    }
+
+    /// instantiates a new contract from `code_id`, the client keeps talking to `contract`
+    pub fn instantiate(
+        &self,
+        code_id: u64,
+        msg: &InstantiateMsg,
+        label: impl Into<String>,
+        admin: Option<String>,
+    ) -> Result<TxResponse, ClientError> {
+        let msg = proto::MsgInstantiateContract {
+            sender: self.signer.address(),
+            admin: admin.unwrap_or_default(),
+            code_id,
+            label: label.into(),
+            msg: to_json_vec(msg)?,
+            funds: vec![],
+        };
+        self.broadcast(MSG_INSTANTIATE_CONTRACT, msg.encode_to_vec())
+    }
+
+    fn broadcast(&self, type_url: &str, value: Vec<u8>) -> Result<TxResponse, ClientError> {
+        let account = self.transport.account(&self.signer.address())?;
+        let body_bytes = proto::TxBody {
+            messages: vec![proto::Any {
+                type_url: type_url.into(),
+                value,
+            }],
+            memo: String::new(),
+        }
+        .encode_to_vec();
+        let auth_info_bytes = proto::AuthInfo {
+            signer_infos: vec![proto::SignerInfo {
+                public_key: Some(proto::Any {
+                    type_url: SECP256K1_PUB_KEY.into(),
+                    value: proto::PubKey {
+                        key: self.signer.public_key(),
+                    }
+                    .encode_to_vec(),
+                }),
+                mode_info: Some(proto::ModeInfo {
+                    single: Some(proto::ModeInfoSingle {
+                        mode: SIGN_MODE_DIRECT,
+                    }),
+                }),
+                sequence: account.sequence,
+            }],
+            fee: Some(proto::Fee {
+                amount: self.fee.iter().map(Into::into).collect(),
+                gas_limit: self.gas_limit,
+            }),
+        }
+        .encode_to_vec();
+        let sign_doc = proto::SignDoc {
+            body_bytes: body_bytes.clone(),
+            auth_info_bytes: auth_info_bytes.clone(),
+            chain_id: self.chain_id.clone(),
+            account_number: account.account_number,
+        };
+        let signature = self.signer.sign(&sign_doc.encode_to_vec())?;
+        let tx = proto::TxRaw {
+            body_bytes,
+            auth_info_bytes,
+            signatures: vec![signature],
+        };
+
+        let res = self.transport.broadcast(&tx.encode_to_vec())?;
+        if res.code != 0 {
+            return Err(ClientError::TxFailed {
+                code: res.code,
+                log: res.raw_log,
+            });
+        }
+        Ok(res)
+    }
+}
@@ -0,0 +1,309 @@
+//! Payouts forwarded to another chain over ICS-20.
+//!
+//! A transfer with `Forward` instructions pays the receiver with an ICS-20 `MsgTransfer` when
+//! confirmed. `Fund` accepts them as part of the transfer id, so a receiver matching the id it
+//! expects agreed to them, and the receiver alone can set or clear them later with `SetForward`.
+//! Swaps over IBC reject them, the maker never checks the id of the source leg. The message
+//! carries an `ibc_callback` memo so the ibc-hooks middleware reports the outcome of the packet
+//! back through `sudo`. A transfer that fails when sent, is acknowledged with an error or times
+//! out is credited to a balance the receiver can `Claim`.
+use cosmwasm_std::{
+    entry_point, Addr, BankMsg, Binary, Coin, CosmosMsg, DepsMut, Env, Reply, Response, StdError,
+    StdResult, Storage, SubMsg, SubMsgResult,
+};
+use cosmwasm_storage::{bucket, bucket_read, singleton, singleton_read, Bucket, ReadonlyBucket};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config_read, release, reserve, transfers, transfers_read, Asset, ContractError, TransferId,
+    TransferRecord, TransferStatus,
+};
+
+pub static FORWARD_KEY: &[u8] = b"forwards";
+pub static FORWARD_SEQ_KEY: &[u8] = b"forward_seq";
+pub static CLAIMABLE_KEY: &[u8] = b"claimable";
+
+const MSG_TRANSFER: &str = "/ibc.applications.transfer.v1.MsgTransfer";
+
+/// Where the receiver wants the payout delivered
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Forward {
+    /// the ICS-20 channel on this chain
+    pub channel: String,
+    pub remote_address: String,
+    /// the time the transfer may take once confirmed, in seconds, at most the longest timelock
+    pub timeout: u64,
+}
+
+impl Forward {
+    /// forwards carry native coins over a channel, within a time
+    pub fn validate(&self, asset: &Asset, max_timeout: u64) -> Result<(), ContractError> {
+        if *asset != Asset::Native
+            || self.channel.is_empty()
+            || !(1..=max_timeout).contains(&self.timeout)
+        {
+            return Err(ContractError::InvalidForward);
+        }
+        Ok(())
+    }
+}
+
+/// A forwarded payout waiting for the outcome of its transfer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct PendingForward {
+    pub transfer_id: TransferId,
+    pub receiver: Addr,
+    pub coin: Coin,
+}
+
+/// The outcome of a packet as reported by the ibc-hooks middleware
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IbcLifecycleComplete {
+    IbcAck {
+        channel: String,
+        sequence: u64,
+        ack: String,
+        success: bool,
+    },
+    IbcTimeout {
+        channel: String,
+        sequence: u64,
+    },
+}
+
+/// forwards keyed by reply id until sent, then by channel and packet sequence
+pub fn forwards<'a>(storage: &'a mut dyn Storage, namespace: &[u8]) -> Bucket<'a, PendingForward> {
+    Bucket::multilevel(storage, &[FORWARD_KEY, namespace])
+}
+
+pub fn forwards_read<'a>(
+    storage: &'a dyn Storage,
+    namespace: &[u8],
+) -> ReadonlyBucket<'a, PendingForward> {
+    ReadonlyBucket::multilevel(storage, &[FORWARD_KEY, namespace])
+}
+
+pub fn claimable(storage: &mut dyn Storage) -> Bucket<'_, Vec<Coin>> {
+    bucket(storage, CLAIMABLE_KEY)
+}
+
+pub fn claimable_read(storage: &dyn Storage) -> ReadonlyBucket<'_, Vec<Coin>> {
+    bucket_read(storage, CLAIMABLE_KEY)
+}
+
+fn credit(storage: &mut dyn Storage, forward: PendingForward) -> StdResult<()> {
+    reserve(storage, &Asset::Native, &forward.coin)?;
+    claimable(storage).update(forward.receiver.as_bytes(), |balance| -> StdResult<_> {
+        let mut balance = balance.unwrap_or_default();
+        match balance.iter_mut().find(|c| c.denom == forward.coin.denom) {
+            Some(total) => total.amount += forward.coin.amount,
+            None => balance.push(forward.coin),
+        }
+        Ok(balance)
+    })?;
+    Ok(())
+}
+
+/// sets where the payout of a pending transfer goes once confirmed, only the receiver chooses it
+pub fn set_forward(
+    storage: &mut dyn Storage,
+    caller: &Addr,
+    transfer_id: TransferId,
+    forward: Option<Forward>,
+) -> Result<Response, ContractError> {
+    let mut transfer = transfers_read(storage)
+        .may_load(&transfer_id)?
+        .ok_or(ContractError::TransferNotExists)?;
+    if transfer.receiver != *caller {
+        return Err(ContractError::Unauthorized);
+    }
+    if transfer.status != TransferStatus::Pending {
+        return Err(ContractError::TransferNotPending);
+    }
+    if let Some(forward) = &forward {
+        forward.validate(&transfer.asset, config_read(storage).load()?.max_timelock)?;
+    }
+    let res = Response::new()
+        .add_attribute("action", "set_forward")
+        .add_attribute("transfer_id", hex::encode(transfer_id))
+        .add_attribute(
+            "remote_address",
+            forward
+                .as_ref()
+                .map(|forward| forward.remote_address.as_str())
+                .unwrap_or_default(),
+        );
+    transfer.forward = forward;
+    transfers(storage).save(&transfer_id, &transfer)?;
+    Ok(res)
+}
+
+/// builds the transfer paying the receiver of a confirmed `record` on the forward chain
+pub fn forward_payout(
+    storage: &mut dyn Storage,
+    env: &Env,
+    transfer_id: TransferId,
+    record: &TransferRecord,
+    forward: &Forward,
+) -> StdResult<SubMsg> {
+    let reply_id = singleton_read::<u64>(storage, FORWARD_SEQ_KEY)
+        .may_load()?
+        .unwrap_or_default()
+        + 1;
+    singleton(storage, FORWARD_SEQ_KEY).save(&reply_id)?;
+    forwards(storage, b"reply").save(
+        &reply_id.to_be_bytes(),
+        &PendingForward {
+            transfer_id,
+            receiver: record.receiver.clone(),
+            coin: record.coin.clone(),
+        },
+    )?;
+    let sender = env.contract.address.as_str();
+    let memo = format!(r#"{{"ibc_callback":"{sender}"}}"#);
+    let timeout = forward
+        .timeout
+        .checked_mul(1_000_000_000)
+        .and_then(|timeout| env.block.time.nanos().checked_add(timeout))
+        .ok_or_else(|| StdError::generic_err("forward timeout out of range"))?;
+    let msg = CosmosMsg::Stargate {
+        type_url: MSG_TRANSFER.into(),
+        value: encode_msg_transfer(forward, &record.coin, sender, timeout, &memo),
+    };
+    Ok(SubMsg::reply_always(msg, reply_id))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    let key = msg.id.to_be_bytes();
+    let forward = forwards_read(deps.storage, b"reply").load(&key)?;
+    forwards(deps.storage, b"reply").remove(&key);
+    let res = Response::new().add_attribute("transfer_id", hex::encode(forward.transfer_id));
+    match msg.result {
+        SubMsgResult::Ok(response) => {
+            let packet = response
+                .events
+                .iter()
+                .find(|event| event.ty == "send_packet")
+                .ok_or_else(|| StdError::generic_err("no send_packet event"))?;
+            let attr = |key: &str| {
+                packet
+                    .attributes
+                    .iter()
+                    .find(|attr| attr.key == key)
+                    .map(|attr| attr.value.clone())
+                    .ok_or_else(|| StdError::generic_err(format!("no {key} attribute")))
+            };
+            let channel = attr("packet_src_channel")?;
+            let sequence: u64 = attr("packet_sequence")?
+                .parse()
+                .map_err(|_| StdError::generic_err("invalid packet_sequence"))?;
+            forwards(deps.storage, channel.as_bytes()).save(&sequence.to_be_bytes(), &forward)?;
+            Ok(res
+                .add_attribute("action", "forward")
+                .add_attribute("channel", channel)
+                .add_attribute("sequence", sequence.to_string()))
+        }
+        SubMsgResult::Err(error) => {
+            credit(deps.storage, forward)?;
+            Ok(res
+                .add_attribute("action", "forward_failed")
+                .add_attribute("error", error))
+        }
+    }
+}
+
+/// settles a forwarded payout, crediting the receiver unless it was delivered
+pub fn complete_forward(
+    storage: &mut dyn Storage,
+    outcome: IbcLifecycleComplete,
+) -> Result<Response, ContractError> {
+    let (channel, sequence, delivered) = match outcome {
+        IbcLifecycleComplete::IbcAck {
+            channel,
+            sequence,
+            success,
+            ..
+        } => (channel, sequence, success),
+        IbcLifecycleComplete::IbcTimeout { channel, sequence } => (channel, sequence, false),
+    };
+    let key = sequence.to_be_bytes();
+    let res = Response::new()
+        .add_attribute("action", "forward_complete")
+        .add_attribute("channel", &channel)
+        .add_attribute("sequence", sequence.to_string())
+        .add_attribute("delivered", delivered.to_string());
+    // packets not sent for a forward are not ours to settle
+    let forward = match forwards_read(storage, channel.as_bytes()).may_load(&key)? {
+        Some(forward) => forward,
+        None => return Ok(res),
+    };
+    forwards(storage, channel.as_bytes()).remove(&key);
+    let res = res.add_attribute("transfer_id", hex::encode(forward.transfer_id));
+    if !delivered {
+        credit(storage, forward)?;
+    }
+    Ok(res)
+}
+
+/// pays out the claimable balance of `caller`
+pub fn claim(storage: &mut dyn Storage, caller: &Addr) -> Result<Response, ContractError> {
+    let balance = claimable_read(storage)
+        .may_load(caller.as_bytes())?
+        .unwrap_or_default();
+    if balance.is_empty() {
+        return Err(ContractError::NothingToClaim);
+    }
+    claimable(storage).remove(caller.as_bytes());
+    for coin in &balance {
+        release(storage, coin)?;
+    }
+    Ok(Response::new()
+        .add_message(BankMsg::Send {
+            to_address: caller.to_string(),
+            amount: balance,
+        })
+        .add_attribute("action", "claim"))
+}
+
+fn encode_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn encode_bytes(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    if !bytes.is_empty() {
+        encode_varint(buf, field << 3 | 2);
+        encode_varint(buf, bytes.len() as u64);
+        buf.extend_from_slice(bytes);
+    }
+}
+
+/// the protobuf encoding of `ibc.applications.transfer.v1.MsgTransfer`
+fn encode_msg_transfer(
+    forward: &Forward,
+    coin: &Coin,
+    sender: &str,
+    timeout_timestamp: u64,
+    memo: &str,
+) -> Binary {
+    let mut token = vec![];
+    encode_bytes(&mut token, 1, coin.denom.as_bytes());
+    encode_bytes(&mut token, 2, coin.amount.to_string().as_bytes());
+
+    let mut buf = vec![];
+    encode_bytes(&mut buf, 1, b"transfer");
+    encode_bytes(&mut buf, 2, forward.channel.as_bytes());
+    encode_bytes(&mut buf, 3, &token);
+    encode_bytes(&mut buf, 4, sender.as_bytes());
+    encode_bytes(&mut buf, 5, forward.remote_address.as_bytes());
+    encode_varint(&mut buf, 7 << 3);
+    encode_varint(&mut buf, timeout_timestamp);
+    encode_bytes(&mut buf, 8, memo.as_bytes());
+    buf.into()
+}
@@ -0,0 +1,184 @@
+//! The JSON encoding of hashlocks, secrets and transfer ids.
+//!
+//! 32-byte values are written as hex strings. Hex, with or without `0x`, base64 and the
+//! 32-number arrays of the previous message format are all accepted when parsing. Fields opt in
+//! with `#[serde(with = "hash32")]` and `#[schemars(with = "Hash32")]`, or the matching
+//! `Option`, `Vec` or tuple of `Hash32` for the schema.
+use std::fmt;
+
+use cosmwasm_std::Binary;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Metadata, Schema, SchemaObject};
+use schemars::JsonSchema;
+use serde::de::{self, DeserializeOwned, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::TransferMsg;
+
+/// A hashlock, secret or transfer id as it appears in JSON
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Hash32(pub [u8; 32]);
+
+impl From<[u8; 32]> for Hash32 {
+    fn from(bytes: [u8; 32]) -> Self {
+        Hash32(bytes)
+    }
+}
+
+impl From<Hash32> for [u8; 32] {
+    fn from(hash: Hash32) -> Self {
+        hash.0
+    }
+}
+
+impl fmt::Display for Hash32 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl Serialize for Hash32 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Hash32 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(Hash32Visitor)
+    }
+}
+
+struct Hash32Visitor;
+
+impl<'de> Visitor<'de> for Hash32Visitor {
+    type Value = Hash32;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("32 bytes as hex, base64 or an array of numbers")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Hash32, E> {
+        let bytes = match value.strip_prefix("0x").unwrap_or(value) {
+            hex if hex.len() == 64 => hex::decode(hex).map_err(E::custom)?,
+            _ => Binary::from_base64(value).map_err(E::custom)?.to_vec(),
+        };
+        let len = bytes.len();
+        bytes
+            .try_into()
+            .map(Hash32)
+            .map_err(|_| E::invalid_length(len, &self))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Hash32, A::Error> {
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+        }
+        if seq.next_element::<u8>()?.is_some() {
+            return Err(de::Error::invalid_length(33, &self));
+        }
+        Ok(Hash32(bytes))
+    }
+}
+
+impl JsonSchema for Hash32 {
+    fn schema_name() -> String {
+        "Hash32".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "32 bytes written as hex, parsed from hex, base64 or the 32-number array \
+                     of the previous format"
+                        .into(),
+                ),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// A value holding 32-byte fields and its JSON representation
+pub trait HexRepr: Sized {
+    type Repr: Serialize + DeserializeOwned;
+
+    fn to_repr(&self) -> Self::Repr;
+    fn from_repr(repr: Self::Repr) -> Self;
+}
+
+impl HexRepr for [u8; 32] {
+    type Repr = Hash32;
+
+    fn to_repr(&self) -> Hash32 {
+        Hash32(*self)
+    }
+
+    fn from_repr(repr: Hash32) -> Self {
+        repr.0
+    }
+}
+
+impl<T: HexRepr> HexRepr for Option<T> {
+    type Repr = Option<T::Repr>;
+
+    fn to_repr(&self) -> Self::Repr {
+        self.as_ref().map(T::to_repr)
+    }
+
+    fn from_repr(repr: Self::Repr) -> Self {
+        repr.map(T::from_repr)
+    }
+}
+
+impl<T: HexRepr> HexRepr for Vec<T> {
+    type Repr = Vec<T::Repr>;
+
+    fn to_repr(&self) -> Self::Repr {
+        self.iter().map(T::to_repr).collect()
+    }
+
+    fn from_repr(repr: Self::Repr) -> Self {
+        repr.into_iter().map(T::from_repr).collect()
+    }
+}
+
+impl<A: HexRepr, B: HexRepr> HexRepr for (A, B) {
+    type Repr = (A::Repr, B::Repr);
+
+    fn to_repr(&self) -> Self::Repr {
+        (self.0.to_repr(), self.1.to_repr())
+    }
+
+    fn from_repr(repr: Self::Repr) -> Self {
+        (A::from_repr(repr.0), B::from_repr(repr.1))
+    }
+}
+
+/// encodes its own hashlock
+impl HexRepr for TransferMsg {
+    type Repr = TransferMsg;
+
+    fn to_repr(&self) -> TransferMsg {
+        self.clone()
+    }
+
+    fn from_repr(repr: TransferMsg) -> Self {
+        repr
+    }
+}
+
+pub fn serialize<T: HexRepr, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    value.to_repr().serialize(serializer)
+}
+
+pub fn deserialize<'de, T: HexRepr, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+    T::Repr::deserialize(deserializer).map(T::from_repr)
+}
@@ -0,0 +1,629 @@
+//! Swaps between two instances of the contract connected by an IBC channel.
+//!
+//! Funding an outbound leg with `IbcTransferOut` sends an `Open` packet, the counterparty opens
+//! the mirrored leg paid from the liquidity of the maker, within the rates the maker set for the
+//! pair of denoms. An `Open` packet that fails leaves no state behind. Confirming the mirrored
+//! leg sends the secret back in a `Reveal` packet, which confirms the outbound leg. An outbound
+//! leg whose `Open` packet fails or times out is refunded at once.
+use cosmwasm_std::{
+    entry_point, from_json, to_json_binary, to_json_string, Addr, Binary, Coin, CosmosMsg, Decimal,
+    DepsMut, Env, Ibc3ChannelOpenResponse, IbcBasicResponse, IbcChannel, IbcChannelCloseMsg,
+    IbcChannelConnectMsg, IbcChannelOpenMsg, IbcChannelOpenResponse, IbcMsg, IbcOrder,
+    IbcPacketAckMsg, IbcPacketReceiveMsg, IbcPacketTimeoutMsg, IbcReceiveResponse, IbcTimeout,
+    MessageInfo, Response, StdError, StdResult, Storage, Timestamp, Uint128,
+};
+use cosmwasm_storage::{bucket, bucket_read, Bucket, ReadonlyBucket};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utils::{HashLock, SecretKey};
+
+use crate::{
+    assert_not_paused, config, confirm_transfer, deduct_payment, excess_payment, fund_transfer,
+    hash32, refund_in_full, release, reserve, transfers, transfers_read, Asset, Config,
+    ContractError, CrossChainLeg, Hash32, Payouts, TimelockKind, TransferId, TransferMsg,
+    TransferOut, TransferRecord,
+};
+
+pub const IBC_VERSION: &str = "atomic-swap-1";
+
+pub static CHANNEL_KEY: &[u8] = b"channels";
+pub static LIQUIDITY_KEY: &[u8] = b"liquidity";
+
+/// How a transfer is linked to its leg on the other end of an IBC channel
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IbcLeg {
+    /// funded here, the mirrored leg is opened on the counterparty
+    Source {
+        channel: String,
+        /// the lock of the mirrored leg, a unix timestamp in seconds
+        remote_timelock: u64,
+        /// set once the counterparty acknowledged the mirrored leg
+        #[serde(default, with = "hash32")]
+        #[schemars(with = "Option<Hash32>")]
+        remote_id: Option<TransferId>,
+    },
+    /// opened from the liquidity of a maker to mirror a transfer on the counterparty
+    Mirror {
+        channel: String,
+        /// the lock of the source leg, a unix timestamp in seconds
+        remote_timelock: u64,
+        #[serde(with = "hash32")]
+        #[schemars(with = "Hash32")]
+        remote_id: TransferId,
+    },
+}
+
+/// The funds of a maker for the mirrored legs opened over a channel
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct Liquidity {
+    /// the address of the maker on the counterparty chain, the source legs need to pay it
+    pub remote_receiver: String,
+    pub funds: Vec<Coin>,
+    /// the terms mirrored legs are paid at, pairs without a rate are not paid
+    #[serde(default)]
+    pub rates: Vec<MakerRate>,
+}
+
+/// The most a maker pays for a mirrored leg of a pair of denoms
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct MakerRate {
+    /// the denom of the source leg on the counterparty chain
+    pub src_denom: String,
+    /// the denom paid on this chain
+    pub dst_denom: String,
+    /// paid per unit of the source leg
+    pub rate: Decimal,
+    /// the most paid by a single mirrored leg
+    pub max_amount: Uint128,
+}
+
+impl Liquidity {
+    /// checks a mirrored leg paying `coin` for a source leg of `src_coin` against the rates
+    fn assert_quote(&self, src_coin: &Coin, coin: &Coin) -> Result<(), ContractError> {
+        let rate = self
+            .rates
+            .iter()
+            .find(|rate| rate.src_denom == src_coin.denom && rate.dst_denom == coin.denom)
+            .ok_or(ContractError::NoMakerRate)?;
+        let quoted = src_coin
+            .amount
+            .checked_mul_floor(rate.rate)
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+        if coin.amount > quoted.min(rate.max_amount) {
+            return Err(ContractError::QuoteExceeded {
+                max: quoted.min(rate.max_amount),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapPacket {
+    /// opens the mirrored leg of `src_transfer_id`
+    Open {
+        src_transfer_id: TransferId,
+        src_timelock: u64,
+        /// the receiver of the source leg
+        src_receiver: String,
+        /// the maker paying the mirrored leg
+        maker: String,
+        receiver: String,
+        coin: Coin,
+        hashlock: HashLock,
+        timelock: u64,
+        /// the coin of the source leg, priced by the rates of the maker
+        #[serde(default)]
+        src_coin: Option<Coin>,
+    },
+    /// confirms the source leg `transfer_id`
+    Reveal {
+        transfer_id: TransferId,
+        secret: SecretKey,
+    },
+}
+
+/// The acknowledgement of a `SwapPacket`, the result of `Open` is the mirrored transfer id
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapAck {
+    Result(Binary),
+    Error(String),
+}
+
+pub fn channels(storage: &mut dyn Storage) -> Bucket<'_, IbcChannel> {
+    bucket(storage, CHANNEL_KEY)
+}
+
+pub fn channels_read(storage: &dyn Storage) -> ReadonlyBucket<'_, IbcChannel> {
+    bucket_read(storage, CHANNEL_KEY)
+}
+
+/// the liquidity of every maker on `channel`
+pub fn liquidity<'a>(storage: &'a mut dyn Storage, channel: &str) -> Bucket<'a, Liquidity> {
+    Bucket::multilevel(storage, &[LIQUIDITY_KEY, channel.as_bytes()])
+}
+
+pub fn liquidity_read<'a>(
+    storage: &'a dyn Storage,
+    channel: &str,
+) -> ReadonlyBucket<'a, Liquidity> {
+    ReadonlyBucket::multilevel(storage, &[LIQUIDITY_KEY, channel.as_bytes()])
+}
+
+fn assert_channel(channel: &IbcChannel) -> Result<(), ContractError> {
+    if channel.order != IbcOrder::Unordered || channel.version != IBC_VERSION {
+        return Err(ContractError::InvalidIbcChannel {
+            version: IBC_VERSION.into(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_channel_open(
+    _deps: DepsMut,
+    _env: Env,
+    msg: IbcChannelOpenMsg,
+) -> Result<IbcChannelOpenResponse, ContractError> {
+    assert_channel(msg.channel())?;
+    if let Some(version) = msg.counterparty_version() {
+        if version != IBC_VERSION {
+            return Err(ContractError::InvalidIbcChannel {
+                version: IBC_VERSION.into(),
+            });
+        }
+    }
+    Ok(Some(Ibc3ChannelOpenResponse {
+        version: IBC_VERSION.into(),
+    }))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_channel_connect(
+    deps: DepsMut,
+    _env: Env,
+    msg: IbcChannelConnectMsg,
+) -> Result<IbcBasicResponse, ContractError> {
+    let channel = msg.channel();
+    assert_channel(channel)?;
+    channels(deps.storage).save(channel.endpoint.channel_id.as_bytes(), channel)?;
+    Ok(IbcBasicResponse::new()
+        .add_attribute("action", "ibc_connect")
+        .add_attribute("channel", &channel.endpoint.channel_id))
+}
+
+/// pending source legs are refunded by the timeouts of their packets
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_channel_close(
+    deps: DepsMut,
+    _env: Env,
+    msg: IbcChannelCloseMsg,
+) -> Result<IbcBasicResponse, ContractError> {
+    let channel = msg.channel();
+    channels(deps.storage).remove(channel.endpoint.channel_id.as_bytes());
+    Ok(IbcBasicResponse::new()
+        .add_attribute("action", "ibc_close")
+        .add_attribute("channel", &channel.endpoint.channel_id))
+}
+
+/// failures are acknowledged as errors, so the source leg gets refunded, and write nothing as
+/// every check comes before the first write
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_packet_receive(
+    deps: DepsMut,
+    env: Env,
+    msg: IbcPacketReceiveMsg,
+) -> Result<IbcReceiveResponse, ContractError> {
+    let channel = msg.packet.dest.channel_id;
+    let res = from_json(&msg.packet.data)
+        .map_err(ContractError::from)
+        .and_then(|packet| match packet {
+            SwapPacket::Open {
+                src_transfer_id,
+                src_timelock,
+                src_receiver,
+                maker,
+                receiver,
+                coin,
+                hashlock,
+                timelock,
+                src_coin,
+            } => open_mirror(
+                deps,
+                &env,
+                IbcLeg::Mirror {
+                    channel,
+                    remote_timelock: src_timelock,
+                    remote_id: src_transfer_id,
+                },
+                src_receiver,
+                src_coin,
+                TransferMsg {
+                    sender: maker,
+                    receiver,
+                    coin,
+                    hashlock,
+                    timelock,
+                    timelock_kind: TimelockKind::Timestamp,
+                    tip: None,
+                    // the source chain already checked the hashlock of this swap
+                    allow_hashlock_reuse: true,
+                    forward: None,
+                    referrer: None,
+                    exclusive_window: None,
+                    delegate: None,
+                    premium: None,
+                },
+            ),
+            SwapPacket::Reveal {
+                transfer_id,
+                secret,
+            } => reveal(deps, &env, &msg.relayer, transfer_id, secret),
+        });
+    Ok(match res {
+        Ok(res) => res,
+        Err(err) => IbcReceiveResponse::new()
+            .set_ack(to_json_binary(&SwapAck::Error(err.to_string()))?)
+            .add_attribute("action", "ibc_receive")
+            .add_attribute("error", err.to_string()),
+    })
+}
+
+fn open_mirror(
+    mut deps: DepsMut,
+    env: &Env,
+    ibc: IbcLeg,
+    src_receiver: String,
+    src_coin: Option<Coin>,
+    msg: TransferMsg,
+) -> Result<IbcReceiveResponse, ContractError> {
+    let channel = match &ibc {
+        IbcLeg::Mirror { channel, .. } | IbcLeg::Source { channel, .. } => channel.clone(),
+    };
+    assert_not_paused(deps.storage, |state| state.fund, "fund")?;
+    let maker = deps.api.addr_validate(&msg.sender)?;
+    let mut maker_liquidity = liquidity_read(deps.storage, &channel)
+        .may_load(maker.as_bytes())?
+        .ok_or(ContractError::InsufficientLiquidity)?;
+    // the maker only pays legs mirroring a transfer to its own address
+    if maker_liquidity.remote_receiver != src_receiver {
+        return Err(ContractError::Unauthorized);
+    }
+    maker_liquidity.assert_quote(&src_coin.ok_or(ContractError::NoMakerRate)?, &msg.coin)?;
+    let available = maker_liquidity
+        .funds
+        .iter_mut()
+        .find(|fund| fund.denom == msg.coin.denom)
+        .filter(|fund| fund.amount >= msg.coin.amount)
+        .ok_or(ContractError::InsufficientLiquidity)?;
+    available.amount -= msg.coin.amount;
+    if transfers_read(deps.storage)
+        .may_load(&msg.transfer_id())?
+        .is_some()
+    {
+        return Err(ContractError::TransferExists);
+    }
+
+    // mirrored legs are paid by the maker, so no fee is charged
+    let config_state = Config {
+        fees: vec![],
+        ..config(deps.storage).load()?
+    };
+    let sent = [msg.coin.clone()];
+    let (transfer_id, record) = fund_transfer(
+        deps.branch(),
+        env,
+        &config_state,
+        &sent,
+        Asset::Native,
+        msg,
+        None,
+    )?;
+    transfers(deps.storage).save(
+        &transfer_id,
+        &TransferRecord {
+            ibc: Some(ibc),
+            ..record
+        },
+    )?;
+    liquidity(deps.storage, &channel).save(maker.as_bytes(), &maker_liquidity)?;
+    // the liquidity now backs the locked leg
+    release(deps.storage, &sent[0])?;
+
+    Ok(IbcReceiveResponse::new()
+        .set_ack(to_json_binary(&SwapAck::Result(Binary::from(
+            transfer_id.as_slice(),
+        )))?)
+        .add_attribute("action", "ibc_open")
+        .add_attribute("transfer_id", hex::encode(transfer_id)))
+}
+
+/// the relayer delivering the secret collects the tip
+fn reveal(
+    deps: DepsMut,
+    env: &Env,
+    relayer: &Addr,
+    transfer_id: TransferId,
+    secret: SecretKey,
+) -> Result<IbcReceiveResponse, ContractError> {
+    assert_not_paused(deps.storage, |state| state.confirm, "confirm")?;
+    let config_state = config(deps.storage).load()?;
+    let mut payouts = Payouts::default();
+    confirm_transfer(
+        deps.storage,
+        env,
+        &config_state,
+        relayer,
+        transfer_id,
+        secret,
+        &mut payouts,
+    )?;
+    Ok(IbcReceiveResponse::new()
+        .set_ack(to_json_binary(&SwapAck::Result(Binary::default()))?)
+        .add_submessages(payouts.into_msgs()?)
+        .add_attribute("action", "ibc_reveal")
+        .add_attribute("transfer_id", hex::encode(transfer_id)))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_packet_ack(
+    deps: DepsMut,
+    env: Env,
+    msg: IbcPacketAckMsg,
+) -> Result<IbcBasicResponse, ContractError> {
+    let packet: SwapPacket = from_json(&msg.original_packet.data)?;
+    let ack: SwapAck = from_json(&msg.acknowledgement.data)?;
+    match (packet, ack) {
+        (
+            SwapPacket::Open {
+                src_transfer_id, ..
+            },
+            SwapAck::Result(remote_id),
+        ) => {
+            let remote_id = TransferId::try_from(remote_id.as_slice())
+                .map_err(|_| ContractError::InvalidIbcAck)?;
+            transfers(deps.storage).update(&src_transfer_id, |t| {
+                let mut transfer = t.ok_or(ContractError::TransferNotExists)?;
+                if let Some(IbcLeg::Source { remote_id: id, .. }) = &mut transfer.ibc {
+                    *id = Some(remote_id);
+                }
+                Ok::<_, ContractError>(transfer)
+            })?;
+            Ok(IbcBasicResponse::new()
+                .add_attribute("action", "ibc_opened")
+                .add_attribute("transfer_id", hex::encode(src_transfer_id))
+                .add_attribute("remote_id", hex::encode(remote_id)))
+        }
+        (
+            SwapPacket::Open {
+                src_transfer_id, ..
+            },
+            SwapAck::Error(err),
+        ) => refund_source(deps, &env, src_transfer_id, "ibc_open_failed")
+            .map(|res| res.add_attribute("error", err)),
+        // the secret is public on this chain, the maker can still confirm the source leg
+        (SwapPacket::Reveal { transfer_id, .. }, ack) => Ok(IbcBasicResponse::new()
+            .add_attribute("action", "ibc_revealed")
+            .add_attribute("transfer_id", hex::encode(transfer_id))
+            .add_attribute("success", matches!(ack, SwapAck::Result(_)).to_string())),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_packet_timeout(
+    deps: DepsMut,
+    env: Env,
+    msg: IbcPacketTimeoutMsg,
+) -> Result<IbcBasicResponse, ContractError> {
+    match from_json(&msg.packet.data)? {
+        SwapPacket::Open {
+            src_transfer_id, ..
+        } => refund_source(deps, &env, src_transfer_id, "ibc_open_timeout"),
+        SwapPacket::Reveal { transfer_id, .. } => Ok(IbcBasicResponse::new()
+            .add_attribute("action", "ibc_reveal_timeout")
+            .add_attribute("transfer_id", hex::encode(transfer_id))),
+    }
+}
+
+/// the mirrored leg was never opened, so the source leg, its fee and tip go back to the sender
+fn refund_source(
+    deps: DepsMut,
+    env: &Env,
+    transfer_id: TransferId,
+    action: &str,
+) -> Result<IbcBasicResponse, ContractError> {
+    let mut payouts = Payouts::default();
+    refund_in_full(deps.storage, env, transfer_id, &mut payouts)?;
+    Ok(IbcBasicResponse::new()
+        .add_submessages(payouts.into_msgs()?)
+        .add_attribute("action", action)
+        .add_attribute("transfer_id", hex::encode(transfer_id)))
+}
+
+/// funds the source leg and sends the packet opening the mirrored leg, the mirrored leg is
+/// locked for half of the remaining time so the maker has time to confirm after the reveal
+pub fn transfer_out(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: TransferMsg,
+    leg: TransferOut,
+    channel: String,
+    maker: String,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, |state| state.fund, "fund")?;
+    if channels_read(deps.storage)
+        .may_load(channel.as_bytes())?
+        .is_none()
+    {
+        return Err(ContractError::UnknownChannel { channel });
+    }
+    if msg.timelock_kind != TimelockKind::Timestamp {
+        return Err(ContractError::InvalidIbcTimelock);
+    }
+    // the mirrored leg is paid from liquidity, and an early refund could race its reveal
+    if msg.premium.is_some() {
+        return Err(ContractError::InvalidPremium);
+    }
+    // the maker never checks the id of the source leg, so the sender cannot forward its payout,
+    // and the relayed reveal that confirms the source leg cannot be held off by a window
+    if msg.forward.is_some() || msg.exclusive_window.is_some() || msg.delegate.is_some() {
+        return Err(ContractError::InvalidIbcTerms);
+    }
+    let now = env.block.time.seconds();
+    let remote_timelock = now + msg.timelock.saturating_sub(now) / 2;
+    let remote_coin = Coin {
+        denom: leg.dst_token.clone(),
+        amount: Uint128::try_from(leg.dst_amount).map_err(StdError::from)?,
+    };
+    let packet = SwapPacket::Open {
+        src_transfer_id: msg.transfer_id(),
+        src_timelock: msg.timelock,
+        src_receiver: msg.receiver.clone(),
+        maker,
+        receiver: leg.dst_address.clone(),
+        coin: remote_coin,
+        hashlock: msg.hashlock,
+        timelock: remote_timelock,
+        src_coin: Some(msg.coin.clone()),
+    };
+
+    let config_state = config(deps.storage).load()?;
+    let (transfer_id, record) = fund_transfer(
+        deps.branch(),
+        &env,
+        &config_state,
+        &info.funds,
+        Asset::Native,
+        msg,
+        Some(CrossChainLeg::Out(leg)),
+    )?;
+    let record = TransferRecord {
+        ibc: Some(IbcLeg::Source {
+            channel: channel.clone(),
+            remote_timelock,
+            remote_id: None,
+        }),
+        ..record
+    };
+    transfers(deps.storage).save(&transfer_id, &record)?;
+    let mut remaining = info.funds;
+    deduct_payment(&mut remaining, &record);
+    let mut payouts = Payouts::default();
+    for coin in excess_payment(&config_state, remaining)? {
+        payouts.add(&record, info.sender.to_string(), coin);
+    }
+
+    Ok(Response::new()
+        .add_submessages(payouts.into_msgs()?)
+        .add_message(IbcMsg::SendPacket {
+            channel_id: channel,
+            data: to_json_binary(&packet)?,
+            timeout: IbcTimeout::with_timestamp(Timestamp::from_seconds(remote_timelock)),
+        })
+        .add_events(
+            record
+                .leg
+                .as_ref()
+                .map(|leg| leg.event(&transfer_id, &record)),
+        )
+        .set_data(transfer_id))
+}
+
+/// the packet relaying the secret of a confirmed mirrored leg back to its source leg
+pub fn relay_secret(record: &TransferRecord) -> StdResult<Option<CosmosMsg>> {
+    match &record.ibc {
+        Some(IbcLeg::Mirror {
+            channel,
+            remote_timelock,
+            remote_id,
+        }) => Ok(Some(
+            IbcMsg::SendPacket {
+                channel_id: channel.clone(),
+                data: to_json_binary(&SwapPacket::Reveal {
+                    transfer_id: *remote_id,
+                    secret: record.secret_key,
+                })?,
+                timeout: IbcTimeout::with_timestamp(Timestamp::from_seconds(*remote_timelock)),
+            }
+            .into(),
+        )),
+        _ => Ok(None),
+    }
+}
+
+pub fn provide_liquidity(
+    deps: DepsMut,
+    info: MessageInfo,
+    channel: String,
+    remote_receiver: String,
+) -> Result<Response, ContractError> {
+    let mut maker_liquidity = liquidity_read(deps.storage, &channel)
+        .may_load(info.sender.as_bytes())?
+        .unwrap_or_default();
+    maker_liquidity.remote_receiver = remote_receiver;
+    for sent in info.funds {
+        reserve(deps.storage, &Asset::Native, &sent)?;
+        match maker_liquidity
+            .funds
+            .iter_mut()
+            .find(|fund| fund.denom == sent.denom)
+        {
+            Some(fund) => fund.amount += sent.amount,
+            None => maker_liquidity.funds.push(sent),
+        }
+    }
+    liquidity(deps.storage, &channel).save(info.sender.as_bytes(), &maker_liquidity)?;
+    Ok(Response::new()
+        .add_attribute("action", "provide_liquidity")
+        .add_attribute("channel", channel)
+        .add_attribute("maker", info.sender))
+}
+
+/// replaces the rates the maker pays mirrored legs at over `channel`
+pub fn set_maker_rates(
+    deps: DepsMut,
+    maker: Addr,
+    channel: String,
+    rates: Vec<MakerRate>,
+) -> Result<Response, ContractError> {
+    let mut maker_liquidity = liquidity_read(deps.storage, &channel)
+        .may_load(maker.as_bytes())?
+        .ok_or(ContractError::InsufficientLiquidity)?;
+    let res = Response::new()
+        .add_attribute("action", "set_maker_rates")
+        .add_attribute("channel", &channel)
+        .add_attribute("maker", maker.as_str())
+        .add_attribute("rates", to_json_string(&rates)?);
+    maker_liquidity.rates = rates;
+    liquidity(deps.storage, &channel).save(maker.as_bytes(), &maker_liquidity)?;
+    Ok(res)
+}
+
+pub fn withdraw_liquidity(
+    deps: DepsMut,
+    maker: Addr,
+    channel: String,
+    coin: Coin,
+) -> Result<Response, ContractError> {
+    liquidity(deps.storage, &channel).update(maker.as_bytes(), |l| {
+        let mut maker_liquidity = l.ok_or(ContractError::InsufficientLiquidity)?;
+        let fund = maker_liquidity
+            .funds
+            .iter_mut()
+            .find(|fund| fund.denom == coin.denom)
+            .filter(|fund| fund.amount >= coin.amount)
+            .ok_or(ContractError::InsufficientLiquidity)?;
+        fund.amount -= coin.amount;
+        Ok::<_, ContractError>(maker_liquidity)
+    })?;
+    release(deps.storage, &coin)?;
+    Ok(Response::new()
+        .add_message(Asset::Native.send(maker.to_string(), coin)?)
+        .add_attribute("action", "withdraw_liquidity")
+        .add_attribute("channel", channel)
+        .add_attribute("maker", maker))
+}
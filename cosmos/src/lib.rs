@@ -1,13 +1,18 @@
 #![allow(clippy::derive_partial_eq_without_eq)]
+// cosmwasm-storage is deprecated upstream, but the `Bucket`/`Singleton` layout is kept so that
+// existing contract state stays readable.
+#![allow(deprecated)]
 use cosmwasm_std::{
-    entry_point, to_binary, BankMsg, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdError,
-    StdResult,
+    coin, entry_point, from_json, to_json_binary, to_json_string, BankMsg, Binary, BlockInfo,
+    CosmosMsg, Deps, DepsMut, Env, Event, MessageInfo, Order, Response, StdError, StdResult,
+    SubMsg, Uint128, Uint256, WasmMsg,
 };
+use cw20::{Cw20ExecuteMsg, Cw20QueryMsg, Cw20ReceiveMsg};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sha3::{Digest, Sha3_256};
 
-use cosmwasm_std::{Addr, Coin, Storage};
+use cosmwasm_std::{Addr, Api, Coin, Storage};
 use cosmwasm_storage::{
     bucket, bucket_read, singleton, singleton_read, Bucket, ReadonlyBucket, ReadonlySingleton,
     Singleton,
@@ -16,13 +21,226 @@ use cosmwasm_storage::{
 use thiserror::Error;
 use utils::{try_lock, HashLock, SecretKey};
 
+pub const CONTRACT_NAME: &str = concat!("crates.io:", env!("CARGO_PKG_NAME"));
+pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub type TransferId = [u8; 32];
+
+/// The default shortest lock duration, in seconds
+pub const DEFAULT_MIN_TIMELOCK: u64 = 10 * 60;
+/// The default longest lock duration, in seconds
+pub const DEFAULT_MAX_TIMELOCK: u64 = 30 * 24 * 60 * 60;
+/// The default time a finalized transfer is kept before it can be cleaned up, in seconds
+pub const DEFAULT_CLEANUP_RETENTION: u64 = 30 * 24 * 60 * 60;
+/// The most records removed by a single cleanup
+pub const MAX_CLEANUP_LIMIT: u32 = 30;
+/// The default shortest lock duration, in blocks
+pub const DEFAULT_MIN_HEIGHT_LOCK: u64 = 100;
+/// The default longest lock duration, in blocks
+pub const DEFAULT_MAX_HEIGHT_LOCK: u64 = 432_000;
+
 pub static CONFIG_KEY: &[u8] = b"config";
 pub static TRANSFER_KEY: &[u8] = b"transfers";
+pub static PAUSE_KEY: &[u8] = b"pause";
+pub static HASHLOCK_KEY: &[u8] = b"hashlocks";
+pub static ASSET_LIMITS_KEY: &[u8] = b"asset_limits";
+pub static ASSET_TOTALS_KEY: &[u8] = b"asset_totals";
+pub static FINALIZED_KEY: &[u8] = b"finalized";
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
+    /// the admin of the contract, `None` once ownership is renounced
+    #[serde(default)]
+    pub owner: Option<Addr>,
+    /// the proposed owner waiting to accept ownership
+    #[serde(default)]
+    pub pending_owner: Option<Addr>,
     pub platform: String,
-    pub fee: Coin,
+    /// fee rules by transfer denom, a denom without rule is charged no fee
+    pub fees: Vec<FeeRule>,
+    /// return the fee to the sender when a transfer is refunded
+    pub refund_fee: bool,
+    /// the shortest lock duration accepted by fund, in seconds
+    #[serde(default = "default_min_timelock")]
+    pub min_timelock: u64,
+    /// the longest lock duration accepted by fund, in seconds
+    #[serde(default = "default_max_timelock")]
+    pub max_timelock: u64,
+    /// the shortest lock duration accepted by fund, in blocks
+    #[serde(default = "default_min_height_lock")]
+    pub min_height_lock: u64,
+    /// the longest lock duration accepted by fund, in blocks
+    #[serde(default = "default_max_height_lock")]
+    pub max_height_lock: u64,
+    /// the time a finalized transfer is kept before anyone can clean it up, in seconds
+    #[serde(default = "default_cleanup_retention")]
+    pub cleanup_retention: u64,
+    /// the share of each platform fee kept as a bounty for cleaning up the record, in bps
+    #[serde(default)]
+    pub cleanup_bounty_bps: u16,
+    /// reject funding that does not send exactly the transfer and its fee, instead of
+    /// returning the excess
+    #[serde(default)]
+    pub strict_payment: bool,
+    /// only fund denoms with `AssetLimits`, CW20 tokens are listed by their contract address
+    #[serde(default)]
+    pub restrict_assets: bool,
+    /// share the platform fee by weight, the platform keeps the rest of the rounding
+    #[serde(default)]
+    pub fee_recipients: Vec<FeeRecipient>,
+    /// the share of the platform fee paid to the referrer of a transfer, in bps
+    #[serde(default)]
+    pub referrer_share_bps: u16,
+}
+
+fn default_min_timelock() -> u64 {
+    DEFAULT_MIN_TIMELOCK
+}
+
+fn default_max_timelock() -> u64 {
+    DEFAULT_MAX_TIMELOCK
+}
+
+fn default_min_height_lock() -> u64 {
+    DEFAULT_MIN_HEIGHT_LOCK
+}
+
+fn default_max_height_lock() -> u64 {
+    DEFAULT_MAX_HEIGHT_LOCK
+}
+
+fn default_cleanup_retention() -> u64 {
+    DEFAULT_CLEANUP_RETENTION
+}
+
+impl Config {
+    /// the part of `fee` kept for whoever cleans up the transfer record
+    pub fn bounty_for(&self, fee: Option<&Coin>) -> Option<Coin> {
+        let fee = fee?;
+        let amount = fee
+            .amount
+            .multiply_ratio(self.cleanup_bounty_bps, 10_000u128);
+        (!amount.is_zero()).then(|| Coin::new(amount.u128(), &fee.denom))
+    }
+
+    /// the timelock needs to be in `[now + min, now + max]` of its kind
+    pub fn assert_timelock(
+        &self,
+        block: &BlockInfo,
+        kind: &TimelockKind,
+        timelock: u64,
+    ) -> Result<(), ContractError> {
+        let (min, max) = match kind {
+            TimelockKind::Timestamp => (
+                block.time.seconds().saturating_add(self.min_timelock),
+                block.time.seconds().saturating_add(self.max_timelock),
+            ),
+            TimelockKind::Height => (
+                block.height.saturating_add(self.min_height_lock),
+                block.height.saturating_add(self.max_height_lock),
+            ),
+        };
+        if (min..=max).contains(&timelock) {
+            Ok(())
+        } else {
+            Err(ContractError::InvalidTimelock { min, max })
+        }
+    }
+
+    pub fn assert_owner(&self, sender: &Addr) -> Result<(), ContractError> {
+        if self.owner.as_ref() == Some(sender) {
+            Ok(())
+        } else {
+            Err(ContractError::Unauthorized {})
+        }
+    }
+
+    /// the fee charged for transferring `coin`
+    pub fn fee_for(&self, coin: &Coin) -> Option<Coin> {
+        let rule = self.fees.iter().find(|rule| rule.denom == coin.denom)?;
+        let fee = match &rule.rate {
+            FeeRate::Flat(fee) => fee.clone(),
+            FeeRate::Bps { bps, cap } => {
+                let mut amount = coin.amount.multiply_ratio(*bps, 10_000u128);
+                if let Some(cap) = cap {
+                    amount = amount.min(*cap);
+                }
+                Coin::new(amount.u128(), &coin.denom)
+            }
+        };
+        (!fee.amount.is_zero()).then_some(fee)
+    }
+
+    /// splits a collected fee between the referrer, the fee recipients by weight and the
+    /// platform, which keeps the rounding dust
+    pub fn fee_split(&self, fee: Coin, referrer: Option<&Addr>) -> Vec<(String, Coin)> {
+        let mut shares = vec![];
+        let mut left = fee.amount;
+        if let Some(referrer) = referrer {
+            let amount = fee
+                .amount
+                .multiply_ratio(self.referrer_share_bps, 10_000u128);
+            left -= amount;
+            shares.push((referrer.to_string(), amount));
+        }
+        let total_weight: u64 = self
+            .fee_recipients
+            .iter()
+            .map(|r| u64::from(r.weight))
+            .sum();
+        let split = left;
+        for recipient in &self.fee_recipients {
+            let amount = split.multiply_ratio(recipient.weight, total_weight);
+            left -= amount;
+            shares.push((recipient.address.clone(), amount));
+        }
+        shares.push((self.platform.clone(), left));
+        shares
+            .into_iter()
+            .filter(|(_, amount)| !amount.is_zero())
+            .map(|(to, amount)| {
+                let coin = Coin {
+                    denom: fee.denom.clone(),
+                    amount,
+                };
+                (to, coin)
+            })
+            .collect()
+    }
+}
+
+/// A receiver of a share of the platform fee
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct FeeRecipient {
+    pub address: String,
+    pub weight: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeRule {
+    /// the transfer denom, or the token contract for CW20
+    pub denom: String,
+    pub rate: FeeRate,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeRate {
+    /// a flat fee, which may be in a different denom from the transfer
+    Flat(Coin),
+    /// basis points of the transfer amount with an optional cap, paid in the transfer denom
+    Bps { bps: u16, cap: Option<Uint128> },
+}
+
+/// How the `timelock` of a transfer is measured
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelockKind {
+    /// a unix timestamp in seconds
+    #[default]
+    Timestamp,
+    /// a block height
+    Height,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, JsonSchema)]
@@ -32,33 +250,531 @@ pub enum TransferStatus {
     Refunded,
 }
 
+/// The kind of asset locked by a transfer, for CW20 tokens the `coin.denom` is the token contract
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Asset {
+    #[default]
+    Native,
+    Cw20 {
+        contract: Addr,
+    },
+}
+
+impl Asset {
+    /// builds the message paying `coin` of this asset to `to_address`
+    pub fn send(&self, to_address: String, coin: Coin) -> StdResult<CosmosMsg> {
+        Ok(match self {
+            Asset::Native => BankMsg::Send {
+                to_address,
+                amount: vec![coin],
+            }
+            .into(),
+            Asset::Cw20 { contract } => WasmMsg::Execute {
+                contract_addr: contract.to_string(),
+                msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: to_address,
+                    amount: coin.amount,
+                })?,
+                funds: vec![],
+            }
+            .into(),
+        })
+    }
+}
+
+/// The outbound leg of a cross-chain swap, as in `OBridge.transferOut`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct TransferOut {
+    pub dst_chain_id: u64,
+    /// the receiver on the destination chain, in the encoding of that chain
+    pub dst_address: String,
+    pub bid_id: u64,
+    /// the token on the destination chain, in the encoding of that chain
+    pub dst_token: String,
+    pub dst_amount: Uint256,
+}
+
+/// The inbound leg of a cross-chain swap, as in `OBridge.transferIn`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct TransferIn {
+    pub src_chain_id: u64,
+    /// the id of the outbound transfer on the source chain
+    #[serde(with = "hash32")]
+    #[schemars(with = "Hash32")]
+    pub src_transfer_id: TransferId,
+}
+
+/// The cross-chain metadata matching a transfer with its leg on another chain
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossChainLeg {
+    Out(TransferOut),
+    In(TransferIn),
+}
+
+impl CrossChainLeg {
+    /// the `new_transfer_out` or `new_transfer_in` event, mirroring the `OBridge` logs
+    pub fn event(&self, transfer_id: &TransferId, record: &TransferRecord) -> Event {
+        let event = match self {
+            CrossChainLeg::Out(_) => Event::new("new_transfer_out"),
+            CrossChainLeg::In(_) => Event::new("new_transfer_in"),
+        }
+        .add_attribute("transfer_id", hex::encode(transfer_id))
+        .add_attribute("sender", record.sender.as_str())
+        .add_attribute("receiver", record.receiver.as_str())
+        .add_attribute("coin", record.coin.to_string())
+        .add_attribute("hashlock", hex::encode(record.hashlock))
+        .add_attribute("timelock", record.timelock.to_string());
+        match self {
+            CrossChainLeg::Out(leg) => event
+                .add_attribute("dst_chain_id", leg.dst_chain_id.to_string())
+                .add_attribute("dst_address", &leg.dst_address)
+                .add_attribute("bid_id", leg.bid_id.to_string())
+                .add_attribute("dst_token", &leg.dst_token)
+                .add_attribute("dst_amount", leg.dst_amount.to_string()),
+            CrossChainLeg::In(leg) => event
+                .add_attribute("src_chain_id", leg.src_chain_id.to_string())
+                .add_attribute("src_transfer_id", hex::encode(leg.src_transfer_id)),
+        }
+    }
+}
+
+/// Payments owed by the contract, summed per recipient, asset and denom
+#[derive(Default)]
+struct Payouts {
+    sends: Vec<(String, Asset, Coin)>,
+    /// payouts leaving the chain, each sent on its own
+    forwards: Vec<SubMsg>,
+}
+
+impl Payouts {
+    fn add(&mut self, record: &TransferRecord, to_address: String, coin: Coin) {
+        let asset = record.asset_for(&coin);
+        match self
+            .sends
+            .iter_mut()
+            .find(|(to, a, c)| *to == to_address && *a == asset && c.denom == coin.denom)
+        {
+            Some((_, _, total)) => total.amount += coin.amount,
+            None => self.sends.push((to_address, asset, coin)),
+        }
+    }
+
+    /// native coins to the same recipient share a single bank message
+    fn into_msgs(self) -> StdResult<Vec<SubMsg>> {
+        let mut grouped: Vec<(String, Asset, Vec<Coin>)> = vec![];
+        for (to_address, asset, coin) in self.sends {
+            match grouped
+                .iter_mut()
+                .find(|(to, a, _)| asset == Asset::Native && *a == asset && *to == to_address)
+            {
+                Some((_, _, coins)) => coins.push(coin),
+                None => grouped.push((to_address, asset, vec![coin])),
+            }
+        }
+        grouped
+            .into_iter()
+            .map(|(to_address, asset, mut coins)| {
+                let msg = match asset {
+                    Asset::Native => BankMsg::Send {
+                        to_address,
+                        amount: coins,
+                    }
+                    .into(),
+                    // entries of a token hold a single coin
+                    asset => asset.send(to_address, coins.remove(0))?,
+                };
+                Ok(SubMsg::new(msg))
+            })
+            .chain(self.forwards.into_iter().map(Ok))
+            .collect()
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct TransferRecord {
     pub sender: Addr,
     pub receiver: Addr,
     pub coin: Coin,
+    #[serde(default)]
+    pub asset: Asset,
+    /// the fee charged when funding
+    #[serde(default)]
+    pub fee: Option<Coin>,
+    /// whether the fee goes back to the sender on refund
+    #[serde(default)]
+    pub refund_fee: bool,
+    #[serde(with = "hash32")]
+    #[schemars(with = "Hash32")]
     pub hashlock: HashLock,
     pub timelock: u64,
+    #[serde(default)]
+    pub timelock_kind: TimelockKind,
+    #[serde(with = "hash32")]
+    #[schemars(with = "Hash32")]
     pub secret_key: SecretKey,
     pub status: TransferStatus,
+    /// the block time when the transfer was confirmed or refunded
+    #[serde(default)]
+    pub finalized_at: Option<u64>,
+    /// the part of the fee paid to whoever cleans up the record
+    #[serde(default)]
+    pub bounty: Option<Coin>,
+    /// set when funded as one leg of a cross-chain swap
+    #[serde(default)]
+    pub leg: Option<CrossChainLeg>,
+    /// set when the other leg lives behind an IBC channel
+    #[serde(default)]
+    pub ibc: Option<IbcLeg>,
+    /// paid to whoever submits the confirm
+    #[serde(default)]
+    pub tip: Option<Coin>,
+    /// where the payout of the receiver is delivered over ICS-20, set by the receiver
+    #[serde(default)]
+    pub forward: Option<Forward>,
+    /// earns the referrer share of the platform fee
+    #[serde(default)]
+    pub referrer: Option<Addr>,
+    /// only the receiver or the delegate can confirm before this block time, in seconds
+    #[serde(default)]
+    pub exclusive_until: Option<u64>,
+    /// agreed to by the receiver, through the id or `SetDelegate`
+    #[serde(default)]
+    pub delegate: Option<Addr>,
 }
 
-pub fn config(storage: &mut dyn Storage) -> Singleton<Config> {
+impl TransferRecord {
+    pub fn is_expired(&self, block: &BlockInfo) -> bool {
+        match self.timelock_kind {
+            TimelockKind::Timestamp => block.time.seconds() > self.timelock,
+            TimelockKind::Height => block.height > self.timelock,
+        }
+    }
+
+    /// the transfer asset when `coin` shares its denom, otherwise a native coin
+    pub fn asset_for(&self, coin: &Coin) -> Asset {
+        if coin.denom == self.coin.denom {
+            self.asset.clone()
+        } else {
+            Asset::Native
+        }
+    }
+
+    pub fn send(&self, to_address: String, coin: Coin) -> StdResult<CosmosMsg> {
+        self.asset_for(&coin).send(to_address, coin)
+    }
+
+    /// whether `caller` may confirm, others wait for the end of the exclusive window
+    pub fn assert_can_confirm(
+        &self,
+        block: &BlockInfo,
+        caller: &Addr,
+    ) -> Result<(), ContractError> {
+        match self.exclusive_until {
+            Some(until)
+                if block.time.seconds() < until
+                    && *caller != self.receiver
+                    && self.delegate.as_ref() != Some(caller) =>
+            {
+                Err(ContractError::ExclusiveWindow { until })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// the fee left for the platform once the cleanup bounty is kept
+    pub fn platform_fee(&self) -> Option<Coin> {
+        let mut fee = self.fee.clone()?;
+        if let Some(bounty) = &self.bounty {
+            fee.amount -= bounty.amount;
+        }
+        (!fee.amount.is_zero()).then_some(fee)
+    }
+
+    /// finalized records are kept for `retention` seconds, records finalized before the
+    /// finalized time was tracked can be cleaned up at once
+    pub fn is_removable(&self, now: u64, retention: u64) -> bool {
+        self.status != TransferStatus::Pending
+            && match self.finalized_at {
+                Some(t) => t.saturating_add(retention) <= now,
+                None => true,
+            }
+    }
+}
+
+pub fn config(storage: &mut dyn Storage) -> Singleton<'_, Config> {
     singleton(storage, CONFIG_KEY)
 }
 
-pub fn config_read(storage: &dyn Storage) -> ReadonlySingleton<Config> {
+pub fn config_read(storage: &dyn Storage) -> ReadonlySingleton<'_, Config> {
     singleton_read(storage, CONFIG_KEY)
 }
 
-pub fn transfers(storage: &mut dyn Storage) -> Bucket<TransferRecord> {
+/// Operations stopped by the owner, refunds after expiry stay possible for the sender
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct PauseState {
+    pub fund: bool,
+    pub confirm: bool,
+    /// only the sender can refund while paused, relayed refunds are rejected
+    pub refund: bool,
+}
+
+pub fn pause_state(storage: &mut dyn Storage) -> Singleton<'_, PauseState> {
+    singleton(storage, PAUSE_KEY)
+}
+
+pub fn pause_state_read(storage: &dyn Storage) -> ReadonlySingleton<'_, PauseState> {
+    singleton_read(storage, PAUSE_KEY)
+}
+
+/// nothing is paused until the owner sets a pause state
+pub fn load_pause_state(storage: &dyn Storage) -> StdResult<PauseState> {
+    Ok(pause_state_read(storage).may_load()?.unwrap_or_default())
+}
+
+/// How often a hashlock was funded, a revealed hashlock can no longer lock anything
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct HashlockState {
+    pub transfers: u32,
+    pub revealed: bool,
+}
+
+pub fn hashlocks(storage: &mut dyn Storage) -> Bucket<'_, HashlockState> {
+    bucket(storage, HASHLOCK_KEY)
+}
+
+pub fn hashlocks_read(storage: &dyn Storage) -> ReadonlyBucket<'_, HashlockState> {
+    bucket_read(storage, HASHLOCK_KEY)
+}
+
+/// the state of `hashlock` once it locks another transfer, nothing is saved
+fn tracked_hashlock(
+    storage: &dyn Storage,
+    hashlock: &HashLock,
+    allow_reuse: bool,
+) -> Result<HashlockState, ContractError> {
+    let mut state = hashlocks_read(storage)
+        .may_load(hashlock)?
+        .unwrap_or_default();
+    if state.revealed {
+        return Err(ContractError::HashlockRevealed);
+    }
+    if state.transfers > 0 && !allow_reuse {
+        return Err(ContractError::HashlockReused);
+    }
+    state.transfers += 1;
+    Ok(state)
+}
+
+/// The amounts accepted for a denom, listing a denom adds it to the allowlist
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct AssetLimits {
+    /// the smallest amount of a single transfer
+    pub min: Uint128,
+    /// the largest amount of a single transfer
+    pub max: Option<Uint128>,
+    /// the most locked by all pending transfers together
+    pub tvl_cap: Option<Uint128>,
+}
+
+/// The running totals of a denom
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct AssetTotals {
+    #[serde(default)]
+    pub asset: Asset,
+    /// held for pending transfers, without fees and tips
+    pub locked: Uint128,
+    /// ever paid out to receivers
+    #[serde(default)]
+    pub confirmed: Uint128,
+    /// ever returned to senders
+    #[serde(default)]
+    pub refunded: Uint128,
+    /// owed besides the locked amounts: the fees and tips of pending transfers, cleanup
+    /// bounties, claimable balances and maker liquidity
+    #[serde(default)]
+    pub reserved: Uint128,
+}
+
+/// The totals of a denom against the balance of the contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Solvency {
+    pub denom: String,
+    pub totals: AssetTotals,
+    pub balance: Uint128,
+    /// the balance above what is owed, the owner can sweep it
+    pub surplus: Uint128,
+    /// what is owed above the balance
+    pub shortfall: Uint128,
+}
+
+pub fn asset_limits(storage: &mut dyn Storage) -> Bucket<'_, AssetLimits> {
+    bucket(storage, ASSET_LIMITS_KEY)
+}
+
+pub fn asset_limits_read(storage: &dyn Storage) -> ReadonlyBucket<'_, AssetLimits> {
+    bucket_read(storage, ASSET_LIMITS_KEY)
+}
+
+pub fn asset_totals(storage: &mut dyn Storage) -> Bucket<'_, AssetTotals> {
+    bucket(storage, ASSET_TOTALS_KEY)
+}
+
+pub fn asset_totals_read(storage: &dyn Storage) -> ReadonlyBucket<'_, AssetTotals> {
+    bucket_read(storage, ASSET_TOTALS_KEY)
+}
+
+/// checks `coin` against the limits of its denom and returns the totals with it locked, nothing
+/// is saved
+fn locked_totals(
+    storage: &dyn Storage,
+    config_state: &Config,
+    asset: &Asset,
+    coin: &Coin,
+) -> Result<AssetTotals, ContractError> {
+    let key = coin.denom.as_bytes();
+    let mut totals = asset_totals_read(storage)
+        .may_load(key)?
+        .unwrap_or_default();
+    totals.asset = asset.clone();
+    totals.locked += coin.amount;
+    match asset_limits_read(storage).may_load(key)? {
+        Some(limits) => {
+            if coin.amount < limits.min {
+                return Err(ContractError::AmountBelowMinimum { min: limits.min });
+            }
+            if let Some(max) = limits.max.filter(|max| coin.amount > *max) {
+                return Err(ContractError::AmountAboveMaximum { max });
+            }
+            if let Some(cap) = limits.tvl_cap.filter(|cap| totals.locked > *cap) {
+                return Err(ContractError::TvlCapExceeded { cap });
+            }
+        }
+        None if config_state.restrict_assets => {
+            return Err(ContractError::AssetNotAllowed {
+                denom: coin.denom.clone(),
+            });
+        }
+        None => {}
+    }
+    Ok(totals)
+}
+
+fn update_totals(
+    storage: &mut dyn Storage,
+    denom: &str,
+    action: impl FnOnce(&mut AssetTotals),
+) -> StdResult<()> {
+    asset_totals(storage).update(denom.as_bytes(), |totals| -> StdResult<_> {
+        let mut totals = totals.unwrap_or_default();
+        action(&mut totals);
+        Ok(totals)
+    })?;
+    Ok(())
+}
+
+/// counts `coin` as owed besides the locked amounts
+fn reserve(storage: &mut dyn Storage, asset: &Asset, coin: &Coin) -> StdResult<()> {
+    update_totals(storage, &coin.denom, |totals| {
+        totals.asset = asset.clone();
+        totals.reserved += coin.amount;
+    })
+}
+
+/// `coin` of the reserved amounts was paid out
+fn release(storage: &mut dyn Storage, coin: &Coin) -> StdResult<()> {
+    update_totals(storage, &coin.denom, |totals| {
+        totals.reserved = totals.reserved.saturating_sub(coin.amount);
+    })
+}
+
+/// moves a finalized transfer out of the locked and reserved totals, its cleanup bounty stays
+/// reserved until paid
+fn settle_totals(storage: &mut dyn Storage, transfer: &TransferRecord) -> StdResult<()> {
+    update_totals(storage, &transfer.coin.denom, |totals| {
+        totals.locked = totals.locked.saturating_sub(transfer.coin.amount);
+        if transfer.status == TransferStatus::Confirmed {
+            totals.confirmed += transfer.coin.amount;
+        } else {
+            totals.refunded += transfer.coin.amount;
+        }
+    })?;
+    for coin in transfer.fee.iter().chain(transfer.tip.iter()) {
+        release(storage, coin)?;
+    }
+    if let Some(bounty) = &transfer.bounty {
+        reserve(storage, &transfer.asset_for(bounty), bounty)?;
+    }
+    Ok(())
+}
+
+/// compares the totals of `denom` with the balance of the contract, a denom without totals is
+/// taken for a native coin
+fn solvency(deps: Deps, env: &Env, denom: String) -> StdResult<Solvency> {
+    let totals = asset_totals_read(deps.storage)
+        .may_load(denom.as_bytes())?
+        .unwrap_or_default();
+    let balance = match &totals.asset {
+        Asset::Native => {
+            deps.querier
+                .query_balance(&env.contract.address, &denom)?
+                .amount
+        }
+        Asset::Cw20 { contract } => {
+            let res: cw20::BalanceResponse = deps.querier.query_wasm_smart(
+                contract,
+                &Cw20QueryMsg::Balance {
+                    address: env.contract.address.to_string(),
+                },
+            )?;
+            res.balance
+        }
+    };
+    let owed = totals.locked + totals.reserved;
+    Ok(Solvency {
+        denom,
+        totals,
+        balance,
+        surplus: balance.saturating_sub(owed),
+        shortfall: owed.saturating_sub(balance),
+    })
+}
+
+pub fn transfers(storage: &mut dyn Storage) -> Bucket<'_, TransferRecord> {
     bucket(storage, TRANSFER_KEY)
 }
 
-pub fn transfers_read(storage: &dyn Storage) -> ReadonlyBucket<TransferRecord> {
+pub fn transfers_read(storage: &dyn Storage) -> ReadonlyBucket<'_, TransferRecord> {
     bucket_read(storage, TRANSFER_KEY)
 }
 
+/// finalized transfers by finalized time then id, so cleanups reach the oldest first without
+/// scanning pending ones
+pub fn finalized(storage: &mut dyn Storage) -> Bucket<'_, ()> {
+    bucket(storage, FINALIZED_KEY)
+}
+
+pub fn finalized_read(storage: &dyn Storage) -> ReadonlyBucket<'_, ()> {
+    bucket_read(storage, FINALIZED_KEY)
+}
+
+/// the key of a transfer finalized at `time` in the finalized index
+pub fn finalized_key(time: u64, transfer_id: &[u8]) -> Vec<u8> {
+    [&time.to_be_bytes()[..], transfer_id].concat()
+}
+
+/// lists a finalized transfer in the finalized index, records finalized before the finalized
+/// time was tracked count as finalized at 0
+pub fn index_finalized(
+    storage: &mut dyn Storage,
+    transfer_id: &[u8],
+    record: &TransferRecord,
+) -> StdResult<()> {
+    let time = record.finalized_at.unwrap_or_default();
+    finalized(storage).save(&finalized_key(time, transfer_id), &())
+}
+
 #[derive(Error, Debug, PartialEq)]
 pub enum ContractError {
     #[error("{0}")]
@@ -78,45 +794,478 @@ pub enum ContractError {
 
     #[error("Transfer still locked")]
     TransferLocked,
+
+    #[error("Transfer is not pending")]
+    TransferNotPending,
+
+    #[error("{operation} is paused")]
+    Paused { operation: String },
+
+    #[error("Timelock should be between {min} and {max}")]
+    InvalidTimelock { min: u64, max: u64 },
+
+    #[error("Minimum lock duration is larger than maximum lock duration")]
+    InvalidTimelockBounds,
+
+    #[error("Cleanup bounty should not exceed 10000 bps")]
+    InvalidCleanupBounty,
+
+    #[error("Fee recipients need a positive weight and the referrer share at most 10000 bps")]
+    InvalidFeeSplit,
+
+    #[error("Only the receiver or its delegate can confirm before {until}")]
+    ExclusiveWindow { until: u64 },
+
+    #[error("Sweep exceeds the surplus of {surplus}")]
+    SweepExceedsSurplus { surplus: Uint128 },
+
+    #[error("Received token does not match the transfer coin")]
+    TokenMismatch,
+
+    #[error("Invalid fee rule for {denom}")]
+    InvalidFeeRule { denom: String },
+
+    #[error("Cannot migrate from contract {name}")]
+    MigrateContractMismatch { name: String },
+
+    #[error("Cannot migrate from version {from} to {to}")]
+    MigrateDowngrade { from: String, to: String },
+
+    #[error("Migrating from 0.1.0 needs an owner")]
+    MigrateOwnerMissing,
+
+    #[error("Invalid contract version {0}")]
+    InvalidVersion(String),
+
+    #[error("Transfer already exists")]
+    TransferExists,
+
+    #[error("Only unordered channels with version {version} are supported")]
+    InvalidIbcChannel { version: String },
+
+    #[error("Unknown channel {channel}")]
+    UnknownChannel { channel: String },
+
+    #[error("Only timestamp locks can be mirrored over IBC")]
+    InvalidIbcTimelock,
+
+    #[error("Forwards, exclusive windows and delegates cannot be mirrored over IBC")]
+    InvalidIbcTerms,
+
+    #[error("Invalid IBC acknowledgement")]
+    InvalidIbcAck,
+
+    #[error("Insufficient liquidity")]
+    InsufficientLiquidity,
+
+    #[error("The maker has no rate for the pair of denoms")]
+    NoMakerRate,
+
+    #[error("The maker pays at most {max} for this leg")]
+    QuoteExceeded { max: Uint128 },
+
+    #[error("Sent funds do not exactly match the transfer and its fee")]
+    InexactPayment,
+
+    #[error("Hashlock already used, set allow_hashlock_reuse to lock another leg with it")]
+    HashlockReused,
+
+    #[error("Hashlock already revealed")]
+    HashlockRevealed,
+
+    #[error("Asset {denom} is not allowed")]
+    AssetNotAllowed { denom: String },
+
+    #[error("Transfer amount below the minimum of {min}")]
+    AmountBelowMinimum { min: Uint128 },
+
+    #[error("Transfer amount above the maximum of {max}")]
+    AmountAboveMaximum { max: Uint128 },
+
+    #[error("Transfer would lock more than the cap of {cap}")]
+    TvlCapExceeded { cap: Uint128 },
+
+    #[error("Invalid asset limits, the minimum is above the maximum")]
+    InvalidAssetLimits,
+
+    #[error("Forwarding needs a native coin, a channel and a timeout up to the longest timelock")]
+    InvalidForward,
+
+    #[error("Nothing to claim")]
+    NothingToClaim,
+
+    #[error("A premium needs a positive native coin and a bounded deposit window")]
+    InvalidPremium,
+
+    #[error("No premium is due for the transfer")]
+    NoPremiumDue,
+
+    #[error("The premium was due at {due}")]
+    PremiumOverdue { due: u64 },
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
-    platform: String,
-    fee: Coin,
+    pub platform: String,
+    pub fees: Vec<FeeRule>,
+    pub refund_fee: bool,
+    /// defaults to `DEFAULT_MIN_TIMELOCK`
+    pub min_timelock: Option<u64>,
+    /// defaults to `DEFAULT_MAX_TIMELOCK`
+    pub max_timelock: Option<u64>,
+    /// defaults to `DEFAULT_MIN_HEIGHT_LOCK`
+    pub min_height_lock: Option<u64>,
+    /// defaults to `DEFAULT_MAX_HEIGHT_LOCK`
+    pub max_height_lock: Option<u64>,
+    /// defaults to `DEFAULT_CLEANUP_RETENTION`
+    pub cleanup_retention: Option<u64>,
+    pub cleanup_bounty_bps: Option<u16>,
+    #[serde(default)]
+    pub strict_payment: bool,
+    #[serde(default)]
+    pub restrict_assets: bool,
+    #[serde(default)]
+    pub fee_recipients: Vec<FeeRecipient>,
+    pub referrer_share_bps: Option<u16>,
+}
+
+/// Operations reserved to the chain governance, funds only ever go back to their sender, and
+/// the callbacks of the ibc-hooks middleware
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SudoMsg {
+    /// refunds a pending transfer with its fee and tip, whatever its timelock
+    ForceRefund {
+        #[serde(with = "hash32")]
+        #[schemars(with = "Hash32")]
+        id: TransferId,
+    },
+    SetPlatform {
+        platform: String,
+    },
+    /// fields left empty are unchanged
+    Pause {
+        fund: Option<bool>,
+        confirm: Option<bool>,
+        refund: Option<bool>,
+    },
+    /// the outcome of a forwarded payout
+    IbcLifecycleComplete(IbcLifecycleComplete),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {
+    /// the owner to set when migrating from 0.1.0, which had no owner, required there
+    pub owner: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
     Fund(TransferMsg),
-    Confirm((TransferMsg, SecretKey)),
+    /// fund the outbound leg of a cross-chain swap
+    TransferOut {
+        transfer: TransferMsg,
+        leg: TransferOut,
+    },
+    /// fund the inbound leg of a cross-chain swap
+    TransferIn {
+        transfer: TransferMsg,
+        leg: TransferIn,
+    },
+    /// fund the outbound leg of a swap with another instance of the contract, the mirrored
+    /// leg is paid from the liquidity of `maker` on the other end of `channel`, native coins only
+    IbcTransferOut {
+        transfer: TransferMsg,
+        leg: TransferOut,
+        channel: String,
+        maker: String,
+    },
+    /// deposit the funds paying the mirrored legs opened over `channel`, the source legs need to
+    /// pay `remote_receiver`
+    ProvideLiquidity {
+        channel: String,
+        remote_receiver: String,
+    },
+    WithdrawLiquidity {
+        channel: String,
+        coin: Coin,
+    },
+    /// replaces the rates the caller pays mirrored legs at over `channel`, the caller needs to
+    /// have provided liquidity
+    SetMakerRates {
+        channel: String,
+        rates: Vec<MakerRate>,
+    },
+    Confirm(
+        #[serde(with = "hash32")]
+        #[schemars(with = "(TransferMsg, Hash32)")]
+        (TransferMsg, SecretKey),
+    ),
     Refund(TransferMsg),
+    /// confirm with the transfer id returned in the data of `Fund`
+    ConfirmById {
+        #[serde(with = "hash32")]
+        #[schemars(with = "Hash32")]
+        id: TransferId,
+        #[serde(with = "hash32")]
+        #[schemars(with = "Hash32")]
+        secret: SecretKey,
+    },
+    /// refund with the transfer id returned in the data of `Fund`
+    RefundById {
+        #[serde(with = "hash32")]
+        #[schemars(with = "Hash32")]
+        id: TransferId,
+    },
+    /// fund many transfers with the sum of their deposits, the data is a `Vec<BatchItemResult>`
+    BatchFund {
+        transfers: Vec<TransferMsg>,
+        /// skip failing transfers and return their deposits instead of failing the batch
+        #[serde(default)]
+        best_effort: bool,
+    },
+    /// confirm many transfers by id, the data is a `Vec<BatchItemResult>`
+    BatchConfirm {
+        #[serde(with = "hash32")]
+        #[schemars(with = "Vec<(Hash32, Hash32)>")]
+        items: Vec<(TransferId, SecretKey)>,
+        #[serde(default)]
+        best_effort: bool,
+    },
+    /// refund many transfers by id, the data is a `Vec<BatchItemResult>`
+    BatchRefund {
+        #[serde(with = "hash32")]
+        #[schemars(with = "Vec<Hash32>")]
+        ids: Vec<TransferId>,
+        #[serde(default)]
+        best_effort: bool,
+    },
+    /// fund a transfer with CW20 tokens, the embedded message is a `ReceiveMsg`
+    Receive(Cw20ReceiveMsg),
+    /// owner only, fields left empty are unchanged
+    UpdateConfig {
+        platform: Option<String>,
+        fees: Option<Vec<FeeRule>>,
+        refund_fee: Option<bool>,
+        min_timelock: Option<u64>,
+        max_timelock: Option<u64>,
+        min_height_lock: Option<u64>,
+        max_height_lock: Option<u64>,
+        cleanup_retention: Option<u64>,
+        cleanup_bounty_bps: Option<u16>,
+        strict_payment: Option<bool>,
+        restrict_assets: Option<bool>,
+        fee_recipients: Option<Vec<FeeRecipient>>,
+        referrer_share_bps: Option<u16>,
+    },
+    /// owner only, the proposed owner needs to accept the ownership
+    ProposeOwner {
+        owner: String,
+    },
+    AcceptOwner {},
+    /// owner only, leaves the contract without owner
+    RenounceOwnership {},
+    /// removes the given transfers, or up to `limit` transfers, finalized longer than the
+    /// retention ago and pays their cleanup bounties to the caller
+    Cleanup {
+        #[serde(default, with = "hash32")]
+        #[schemars(with = "Option<Vec<Hash32>>")]
+        ids: Option<Vec<TransferId>>,
+        limit: Option<u32>,
+    },
+    /// owner only, fields left empty are unchanged
+    SetPause {
+        fund: Option<bool>,
+        confirm: Option<bool>,
+        refund: Option<bool>,
+    },
+    /// receiver only, deposits the premium asked by the sender of a pending transfer
+    DepositPremium {
+        #[serde(with = "hash32")]
+        #[schemars(with = "Hash32")]
+        id: TransferId,
+    },
+    /// receiver only, pays a pending transfer on another chain over ICS-20 once confirmed, or on
+    /// this chain again with `None`
+    SetForward {
+        #[serde(with = "hash32")]
+        #[schemars(with = "Hash32")]
+        id: TransferId,
+        forward: Option<Forward>,
+    },
+    /// receiver only, lets `delegate` confirm a pending transfer during its exclusive window, or
+    /// nobody but the receiver with `None`
+    SetDelegate {
+        #[serde(with = "hash32")]
+        #[schemars(with = "Hash32")]
+        id: TransferId,
+        delegate: Option<String>,
+    },
+    /// pays out the forwarded payouts of the caller that failed to leave the chain
+    Claim {},
+    /// owner only, moves the surplus of a denom, the whole surplus by default, to `recipient`
+    /// or the owner
+    Sweep {
+        denom: String,
+        amount: Option<Uint128>,
+        recipient: Option<String>,
+    },
+    /// owner only, lists the denom with its limits or removes it with `None`
+    SetAssetLimits {
+        denom: String,
+        limits: Option<AssetLimits>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
+pub enum QueryMsg {
+    /// the `TransferRecord` of a transfer
+    Transfer(TransferMsg),
+    /// the `TransferRecord` by the transfer id returned in the data of `Fund`
+    TransferById {
+        #[serde(with = "hash32")]
+        #[schemars(with = "Hash32")]
+        id: TransferId,
+    },
+    /// the `Config`
+    Config {},
+    /// the `PauseState`
+    PauseState {},
+    /// the `Liquidity` of a maker for a channel
+    Liquidity { channel: String, maker: String },
+    /// the `HashlockState` of a hashlock, zero transfers if never seen
+    Hashlock {
+        #[serde(with = "hash32")]
+        #[schemars(with = "Hash32")]
+        hashlock: HashLock,
+    },
+    /// the `AssetLimits` of a denom, `None` if not listed
+    AssetLimits { denom: String },
+    /// every listed denom with its `AssetLimits`
+    AssetList {},
+    /// the coins the address can claim
+    Claimable { address: String },
+    /// the `Solvency` of every denom the contract has handled
+    Solvency {},
+    /// the `Premium` linked to a transfer, `None` if it asked for none
+    Premium {
+        #[serde(with = "hash32")]
+        #[schemars(with = "Hash32")]
+        id: TransferId,
+    },
+}
+
+/// The message embedded in `Cw20ReceiveMsg.msg`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    Fund(TransferMsg),
+    TransferOut {
+        transfer: TransferMsg,
+        leg: TransferOut,
+    },
+    TransferIn {
+        transfer: TransferMsg,
+        leg: TransferIn,
+    },
+}
+
+impl ReceiveMsg {
+    fn into_parts(self) -> (TransferMsg, Option<CrossChainLeg>) {
+        match self {
+            ReceiveMsg::Fund(msg) => (msg, None),
+            ReceiveMsg::TransferOut { transfer, leg } => (transfer, Some(CrossChainLeg::Out(leg))),
+            ReceiveMsg::TransferIn { transfer, leg } => (transfer, Some(CrossChainLeg::In(leg))),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
 pub struct TransferMsg {
-    sender: String,
-    receiver: String,
-    coin: Coin,
-    hashlock: HashLock,
-    timelock: u64,
+    pub sender: String,
+    pub receiver: String,
+    pub coin: Coin,
+    #[serde(with = "hash32")]
+    #[schemars(with = "Hash32")]
+    pub hashlock: HashLock,
+    pub timelock: u64,
+    #[serde(default)]
+    pub timelock_kind: TimelockKind,
+    /// paid on top of `coin` to whoever submits the confirm, returned to the sender on refund
+    #[serde(default)]
+    pub tip: Option<Coin>,
+    /// funds a hashlock already used by another leg of the same swap, not part of the id
+    #[serde(default)]
+    pub allow_hashlock_reuse: bool,
+    /// pays the receiver on another chain over ICS-20, native coins only, part of the id when set
+    /// so a receiver matching the id agreed to it
+    #[serde(default)]
+    pub forward: Option<Forward>,
+    /// earns the referrer share of the platform fee, not part of the id
+    #[serde(default)]
+    pub referrer: Option<String>,
+    /// only the receiver or the delegate can confirm during this many seconds after funding,
+    /// not part of the id
+    #[serde(default)]
+    pub exclusive_window: Option<u64>,
+    /// part of the id when set so a receiver matching the id agreed to it
+    #[serde(default)]
+    pub delegate: Option<String>,
+    /// the premium the counterparty deposits to hold it to the swap, not part of the id
+    #[serde(default)]
+    pub premium: Option<PremiumTerms>,
+}
+
+impl TransferMsg {
+    pub fn transfer_id(&self) -> TransferId {
+        keccak256(
+            &self.sender,
+            &self.receiver,
+            &self.coin,
+            &self.hashlock,
+            self.timelock,
+            &self.timelock_kind,
+            self.tip.as_ref(),
+            self.forward.as_ref(),
+            self.delegate.as_ref(),
+        )
+    }
 }
 
+mod batch;
+#[cfg(feature = "client")]
+pub mod client;
+pub use batch::BatchItemResult;
+pub mod forward;
+pub mod hash32;
+pub use forward::{Forward, IbcLifecycleComplete};
+pub use hash32::Hash32;
+pub mod ibc;
+pub use ibc::{IbcLeg, Liquidity, MakerRate};
+pub mod migrations;
+pub mod premium;
+pub use premium::{Premium, PremiumTerms};
 #[cfg(test)]
 mod tests;
 
 fn assert_sent_sufficient_coin(
     sent: &[Coin],
     required_coin: &Coin,
-    fee_coin: &Coin,
+    extra_coins: &[&Coin],
 ) -> Result<(), ContractError> {
-    let required_amount = required_coin.amount.u128();
-    let fee_amount = fee_coin.amount.u128();
-    let sent_sufficient_funds = sent.iter().any(|coin| {
-        coin.denom == required_coin.denom
-            && coin.denom == fee_coin.denom
-            && coin.amount.u128() >= required_amount + fee_amount
+    let mut required = vec![required_coin.clone()];
+    for extra_coin in extra_coins {
+        match required.iter_mut().find(|c| c.denom == extra_coin.denom) {
+            Some(c) => c.amount += extra_coin.amount,
+            None => required.push((*extra_coin).clone()),
+        }
+    }
+    let sent_sufficient_funds = required.iter().all(|required| {
+        sent.iter()
+            .any(|coin| coin.denom == required.denom && coin.amount >= required.amount)
     });
 
     if sent_sufficient_funds {
@@ -126,23 +1275,210 @@ fn assert_sent_sufficient_coin(
     }
 }
 
+/// removes the deposit, the fee and the tip of `record` from `funds`
+fn deduct_payment(funds: &mut [Coin], record: &TransferRecord) {
+    let paid = std::iter::once(&record.coin)
+        .chain(record.fee.as_ref())
+        .chain(record.tip.as_ref());
+    for paid in paid {
+        if let Some(fund) = funds.iter_mut().find(|fund| fund.denom == paid.denom) {
+            fund.amount -= paid.amount;
+        }
+    }
+}
+
+/// the funds left once every transfer is paid, which strict payment rejects
+fn excess_payment(config_state: &Config, mut funds: Vec<Coin>) -> Result<Vec<Coin>, ContractError> {
+    funds.retain(|fund| !fund.amount.is_zero());
+    if config_state.strict_payment && !funds.is_empty() {
+        return Err(ContractError::InexactPayment);
+    }
+    Ok(funds)
+}
+
+fn validate_config(config_state: &Config) -> Result<(), ContractError> {
+    if config_state.min_timelock > config_state.max_timelock
+        || config_state.min_height_lock > config_state.max_height_lock
+    {
+        Err(ContractError::InvalidTimelockBounds)
+    } else if config_state.cleanup_bounty_bps > 10_000 {
+        Err(ContractError::InvalidCleanupBounty)
+    } else if config_state.referrer_share_bps > 10_000
+        || config_state.fee_recipients.iter().any(|r| r.weight == 0)
+    {
+        Err(ContractError::InvalidFeeSplit)
+    } else {
+        Ok(())
+    }
+}
+
+/// normalizes the recipient addresses
+fn validate_fee_recipients(
+    api: &dyn Api,
+    fee_recipients: Vec<FeeRecipient>,
+) -> StdResult<Vec<FeeRecipient>> {
+    fee_recipients
+        .into_iter()
+        .map(|recipient| {
+            Ok(FeeRecipient {
+                address: api.addr_validate(&recipient.address)?.into_string(),
+                ..recipient
+            })
+        })
+        .collect()
+}
+
+/// a token transfer only carries the token, so its flat fee is charged in the token too
+fn validate_fees(deps: Deps, fees: &[FeeRule]) -> Result<(), ContractError> {
+    for rule in fees {
+        let valid = match &rule.rate {
+            FeeRate::Bps { bps, .. } => *bps <= 10_000,
+            FeeRate::Flat(fee) => {
+                fee.denom == rule.denom
+                    || deps.querier.query_wasm_contract_info(&rule.denom).is_err()
+            }
+        };
+        if !valid {
+            return Err(ContractError::InvalidFeeRule {
+                denom: rule.denom.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
     _env: Env,
-    _info: MessageInfo,
+    info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
+    validate_fees(deps.as_ref(), &msg.fees)?;
     let config_state = Config {
-        platform: msg.platform,
-        fee: msg.fee,
+        owner: Some(info.sender),
+        pending_owner: None,
+        platform: deps.api.addr_validate(&msg.platform)?.into(),
+        fees: msg.fees,
+        refund_fee: msg.refund_fee,
+        min_timelock: msg.min_timelock.unwrap_or(DEFAULT_MIN_TIMELOCK),
+        max_timelock: msg.max_timelock.unwrap_or(DEFAULT_MAX_TIMELOCK),
+        min_height_lock: msg.min_height_lock.unwrap_or(DEFAULT_MIN_HEIGHT_LOCK),
+        max_height_lock: msg.max_height_lock.unwrap_or(DEFAULT_MAX_HEIGHT_LOCK),
+        cleanup_retention: msg.cleanup_retention.unwrap_or(DEFAULT_CLEANUP_RETENTION),
+        cleanup_bounty_bps: msg.cleanup_bounty_bps.unwrap_or_default(),
+        strict_payment: msg.strict_payment,
+        restrict_assets: msg.restrict_assets,
+        fee_recipients: validate_fee_recipients(deps.api, msg.fee_recipients)?,
+        referrer_share_bps: msg.referrer_share_bps.unwrap_or_default(),
     };
+    validate_config(&config_state)?;
 
     config(deps.storage).save(&config_state)?;
+    cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
     Ok(Response::default())
 }
 
+fn parse_version(version: &str) -> Result<semver::Version, ContractError> {
+    version
+        .parse()
+        .map_err(|_| ContractError::InvalidVersion(version.into()))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    let owner = msg
+        .owner
+        .map(|owner| deps.api.addr_validate(&owner))
+        .transpose()?;
+    let mut res = Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("to_version", CONTRACT_VERSION);
+
+    match cw2::get_contract_version(deps.storage) {
+        Ok(stored) => {
+            if stored.contract != CONTRACT_NAME {
+                return Err(ContractError::MigrateContractMismatch {
+                    name: stored.contract,
+                });
+            }
+            if parse_version(&stored.version)? > parse_version(CONTRACT_VERSION)? {
+                return Err(ContractError::MigrateDowngrade {
+                    from: stored.version,
+                    to: CONTRACT_VERSION.into(),
+                });
+            }
+            res = res.add_attribute("from_version", stored.version);
+        }
+        Err(_) if migrations::is_v0(deps.storage) => {
+            // without an owner every owner only message would be out of reach for good
+            let owner = owner.ok_or(ContractError::MigrateOwnerMissing)?;
+            let migrated = migrations::from_v0(deps.storage, owner)?;
+            res = res
+                .add_attribute("from_version", "0.1.0")
+                .add_attribute("migrated_transfers", migrated.to_string());
+        }
+        Err(e) => return Err(e.into()),
+    }
+    migrations::rebuild_totals(deps.storage)?;
+    migrations::rebuild_finalized(deps.storage)?;
+
+    cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    Ok(res)
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn sudo(deps: DepsMut, env: Env, msg: SudoMsg) -> Result<Response, ContractError> {
+    let audit = Event::new("sudo");
+    match msg {
+        SudoMsg::ForceRefund { id } => {
+            let mut payouts = Payouts::default();
+            let transfer = refund_in_full(deps.storage, &env, id, &mut payouts)?;
+            Ok(Response::new()
+                .add_submessages(payouts.into_msgs()?)
+                .add_event(
+                    audit
+                        .add_attribute("operation", "force_refund")
+                        .add_attribute("transfer_id", hex::encode(id))
+                        .add_attribute("sender", transfer.sender)
+                        .add_attribute("coin", transfer.coin.to_string()),
+                ))
+        }
+        SudoMsg::SetPlatform { platform } => {
+            let mut config_state = config(deps.storage).load()?;
+            let previous = config_state.platform;
+            config_state.platform = deps.api.addr_validate(&platform)?.into_string();
+            config(deps.storage).save(&config_state)?;
+            Ok(Response::new().add_event(
+                audit
+                    .add_attribute("operation", "set_platform")
+                    .add_attribute("previous", previous)
+                    .add_attribute("platform", config_state.platform),
+            ))
+        }
+        SudoMsg::Pause {
+            fund,
+            confirm,
+            refund,
+        } => {
+            let mut state = load_pause_state(deps.storage)?;
+            state.fund = fund.unwrap_or(state.fund);
+            state.confirm = confirm.unwrap_or(state.confirm);
+            state.refund = refund.unwrap_or(state.refund);
+            pause_state(deps.storage).save(&state)?;
+            Ok(Response::new().add_event(
+                audit
+                    .add_attribute("operation", "pause")
+                    .add_attribute("fund", state.fund.to_string())
+                    .add_attribute("confirm", state.confirm.to_string())
+                    .add_attribute("refund", state.refund.to_string()),
+            ))
+        }
+        SudoMsg::IbcLifecycleComplete(outcome) => forward::complete_forward(deps.storage, outcome),
+    }
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
     deps: DepsMut,
@@ -151,117 +1487,798 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::Fund(msg) => {
-            let TransferMsg {
-                sender,
-                receiver,
-                coin,
-                hashlock,
-                timelock,
-            } = msg;
-            let config_state = config(deps.storage).load()?;
-            assert_sent_sufficient_coin(&info.funds, &coin, &config_state.fee)?;
-            let transfer_id = keccak256(&sender, &receiver, &coin, &hashlock, timelock);
-            let record = TransferRecord {
-                sender: deps.api.addr_validate(&sender)?,
-                receiver: deps.api.addr_validate(&receiver)?,
-                coin,
-                hashlock,
-                timelock,
-                secret_key: [0; 32],
-                status: TransferStatus::Pending,
+        ExecuteMsg::Fund(msg) => fund(
+            deps,
+            env,
+            &info.sender,
+            &info.funds,
+            Asset::Native,
+            msg,
+            None,
+        ),
+        ExecuteMsg::TransferOut { transfer, leg } => fund(
+            deps,
+            env,
+            &info.sender,
+            &info.funds,
+            Asset::Native,
+            transfer,
+            Some(CrossChainLeg::Out(leg)),
+        ),
+        ExecuteMsg::TransferIn { transfer, leg } => fund(
+            deps,
+            env,
+            &info.sender,
+            &info.funds,
+            Asset::Native,
+            transfer,
+            Some(CrossChainLeg::In(leg)),
+        ),
+        ExecuteMsg::Confirm((msg, secret)) => {
+            confirm(deps, env, &info.sender, msg.transfer_id(), secret)
+        }
+        ExecuteMsg::Refund(msg) => refund(deps, env, &info.sender, msg.transfer_id()),
+        ExecuteMsg::ConfirmById { id, secret } => confirm(deps, env, &info.sender, id, secret),
+        ExecuteMsg::Cleanup { ids, limit } => cleanup(deps, env, info.sender, ids, limit),
+        ExecuteMsg::RefundById { id } => refund(deps, env, &info.sender, id),
+        ExecuteMsg::IbcTransferOut {
+            transfer,
+            leg,
+            channel,
+            maker,
+        } => ibc::transfer_out(deps, env, info, transfer, leg, channel, maker),
+        ExecuteMsg::ProvideLiquidity {
+            channel,
+            remote_receiver,
+        } => ibc::provide_liquidity(deps, info, channel, remote_receiver),
+        ExecuteMsg::WithdrawLiquidity { channel, coin } => {
+            ibc::withdraw_liquidity(deps, info.sender, channel, coin)
+        }
+        ExecuteMsg::SetMakerRates { channel, rates } => {
+            ibc::set_maker_rates(deps, info.sender, channel, rates)
+        }
+        ExecuteMsg::BatchFund {
+            transfers,
+            best_effort,
+        } => batch::batch_fund(deps, env, info, transfers, best_effort),
+        ExecuteMsg::BatchConfirm { items, best_effort } => {
+            batch::batch_confirm(deps, env, info.sender, items, best_effort)
+        }
+        ExecuteMsg::BatchRefund { ids, best_effort } => {
+            batch::batch_refund(deps, env, info.sender, ids, best_effort)
+        }
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender,
+            amount,
+            msg,
+        }) => {
+            let (msg, leg) = from_json::<ReceiveMsg>(&msg)?.into_parts();
+            // the token contract calling us is the only proof of what was deposited
+            if msg.coin.denom != info.sender.as_str() {
+                return Err(ContractError::TokenMismatch);
+            }
+            let sent = coin(amount.u128(), info.sender.as_str());
+            let payer = deps.api.addr_validate(&sender)?;
+            fund(
+                deps,
+                env,
+                &payer,
+                &[sent],
+                Asset::Cw20 {
+                    contract: info.sender,
+                },
+                msg,
+                leg,
+            )
+        }
+        ExecuteMsg::UpdateConfig {
+            platform,
+            fees,
+            refund_fee,
+            min_timelock,
+            max_timelock,
+            min_height_lock,
+            max_height_lock,
+            cleanup_retention,
+            cleanup_bounty_bps,
+            strict_payment,
+            restrict_assets,
+            fee_recipients,
+            referrer_share_bps,
+        } => {
+            let mut config_state = config(deps.storage).load()?;
+            config_state.assert_owner(&info.sender)?;
+            let mut res = Response::new().add_attribute("action", "update_config");
+            if let Some(platform) = platform {
+                config_state.platform = deps.api.addr_validate(&platform)?.into();
+                res = res.add_attribute("platform", platform);
+            }
+            if let Some(fees) = fees {
+                validate_fees(deps.as_ref(), &fees)?;
+                res = res.add_attribute("fees", to_json_string(&fees)?);
+                config_state.fees = fees;
+            }
+            if let Some(refund_fee) = refund_fee {
+                config_state.refund_fee = refund_fee;
+                res = res.add_attribute("refund_fee", refund_fee.to_string());
+            }
+            if let Some(min_timelock) = min_timelock {
+                config_state.min_timelock = min_timelock;
+                res = res.add_attribute("min_timelock", min_timelock.to_string());
+            }
+            if let Some(max_timelock) = max_timelock {
+                config_state.max_timelock = max_timelock;
+                res = res.add_attribute("max_timelock", max_timelock.to_string());
+            }
+            if let Some(min_height_lock) = min_height_lock {
+                config_state.min_height_lock = min_height_lock;
+                res = res.add_attribute("min_height_lock", min_height_lock.to_string());
+            }
+            if let Some(max_height_lock) = max_height_lock {
+                config_state.max_height_lock = max_height_lock;
+                res = res.add_attribute("max_height_lock", max_height_lock.to_string());
+            }
+            if let Some(cleanup_retention) = cleanup_retention {
+                config_state.cleanup_retention = cleanup_retention;
+                res = res.add_attribute("cleanup_retention", cleanup_retention.to_string());
+            }
+            if let Some(cleanup_bounty_bps) = cleanup_bounty_bps {
+                config_state.cleanup_bounty_bps = cleanup_bounty_bps;
+                res = res.add_attribute("cleanup_bounty_bps", cleanup_bounty_bps.to_string());
+            }
+            if let Some(strict_payment) = strict_payment {
+                config_state.strict_payment = strict_payment;
+                res = res.add_attribute("strict_payment", strict_payment.to_string());
+            }
+            if let Some(restrict_assets) = restrict_assets {
+                config_state.restrict_assets = restrict_assets;
+                res = res.add_attribute("restrict_assets", restrict_assets.to_string());
+            }
+            if let Some(fee_recipients) = fee_recipients {
+                let fee_recipients = validate_fee_recipients(deps.api, fee_recipients)?;
+                res = res.add_attribute("fee_recipients", to_json_string(&fee_recipients)?);
+                config_state.fee_recipients = fee_recipients;
+            }
+            if let Some(referrer_share_bps) = referrer_share_bps {
+                config_state.referrer_share_bps = referrer_share_bps;
+                res = res.add_attribute("referrer_share_bps", referrer_share_bps.to_string());
+            }
+            validate_config(&config_state)?;
+            config(deps.storage).save(&config_state)?;
+            Ok(res)
+        }
+        ExecuteMsg::ProposeOwner { owner } => {
+            let mut config_state = config(deps.storage).load()?;
+            config_state.assert_owner(&info.sender)?;
+            let owner = deps.api.addr_validate(&owner)?;
+            config_state.pending_owner = Some(owner.clone());
+            config(deps.storage).save(&config_state)?;
+            Ok(Response::new()
+                .add_attribute("action", "propose_owner")
+                .add_attribute("pending_owner", owner))
+        }
+        ExecuteMsg::AcceptOwner {} => {
+            let mut config_state = config(deps.storage).load()?;
+            if config_state.pending_owner.as_ref() != Some(&info.sender) {
+                return Err(ContractError::Unauthorized {});
+            }
+            let previous_owner = config_state.owner.replace(info.sender.clone());
+            config_state.pending_owner = None;
+            config(deps.storage).save(&config_state)?;
+            Ok(Response::new()
+                .add_attribute("action", "ownership_transferred")
+                .add_attribute(
+                    "previous_owner",
+                    previous_owner.map(String::from).unwrap_or_default(),
+                )
+                .add_attribute("owner", info.sender))
+        }
+        ExecuteMsg::RenounceOwnership {} => {
+            let mut config_state = config(deps.storage).load()?;
+            config_state.assert_owner(&info.sender)?;
+            config_state.owner = None;
+            config_state.pending_owner = None;
+            config(deps.storage).save(&config_state)?;
+            Ok(Response::new()
+                .add_attribute("action", "ownership_transferred")
+                .add_attribute("previous_owner", info.sender)
+                .add_attribute("owner", ""))
+        }
+        ExecuteMsg::SetPause {
+            fund,
+            confirm,
+            refund,
+        } => {
+            config(deps.storage).load()?.assert_owner(&info.sender)?;
+            let mut state = load_pause_state(deps.storage)?;
+            state.fund = fund.unwrap_or(state.fund);
+            state.confirm = confirm.unwrap_or(state.confirm);
+            state.refund = refund.unwrap_or(state.refund);
+            pause_state(deps.storage).save(&state)?;
+            Ok(Response::new()
+                .add_attribute("action", "set_pause")
+                .add_attribute("fund", state.fund.to_string())
+                .add_attribute("confirm", state.confirm.to_string())
+                .add_attribute("refund", state.refund.to_string()))
+        }
+        ExecuteMsg::DepositPremium { id } => premium::deposit(deps, env, info, id),
+        ExecuteMsg::SetForward { id, forward } => {
+            forward::set_forward(deps.storage, &info.sender, id, forward)
+        }
+        ExecuteMsg::SetDelegate { id, delegate } => set_delegate(deps, info, id, delegate),
+        ExecuteMsg::Claim {} => forward::claim(deps.storage, &info.sender),
+        ExecuteMsg::Sweep {
+            denom,
+            amount,
+            recipient,
+        } => {
+            config(deps.storage).load()?.assert_owner(&info.sender)?;
+            let state = solvency(deps.as_ref(), &env, denom)?;
+            let amount = amount.unwrap_or(state.surplus);
+            if amount.is_zero() || amount > state.surplus {
+                return Err(ContractError::SweepExceedsSurplus {
+                    surplus: state.surplus,
+                });
+            }
+            let recipient = match recipient {
+                Some(recipient) => deps.api.addr_validate(&recipient)?,
+                None => info.sender,
             };
-            transfers(deps.storage).save(&transfer_id, &record)?;
-            Ok(Response::default())
+            let coin = Coin {
+                denom: state.denom,
+                amount,
+            };
+            Ok(Response::new()
+                .add_message(
+                    state
+                        .totals
+                        .asset
+                        .send(recipient.to_string(), coin.clone())?,
+                )
+                .add_attribute("action", "sweep")
+                .add_attribute("recipient", recipient)
+                .add_attribute("coin", coin.to_string()))
         }
-        ExecuteMsg::Confirm((msg, secret)) => {
-            let TransferMsg {
-                sender,
-                receiver,
-                coin,
-                hashlock,
-                timelock,
-            } = msg;
-            let transfer_id = keccak256(&sender, &receiver, &coin, &hashlock, timelock);
-            transfers(deps.storage).update(&transfer_id, |t| {
-                if let Some(mut transfer) = t {
-                    if try_lock(secret, hashlock) && transfer.status == TransferStatus::Pending {
-                        transfer.secret_key = secret;
-                        transfer.status = TransferStatus::Confirmed;
-                        Ok(transfer)
-                    } else {
-                        Err(ContractError::IncorrectSecret {})
+        ExecuteMsg::SetAssetLimits { denom, limits } => {
+            config(deps.storage).load()?.assert_owner(&info.sender)?;
+            let res = Response::new()
+                .add_attribute("action", "set_asset_limits")
+                .add_attribute("denom", &denom);
+            match limits {
+                Some(limits) => {
+                    if matches!(limits.max, Some(max) if limits.min > max) {
+                        return Err(ContractError::InvalidAssetLimits);
                     }
-                } else {
-                    Err(ContractError::TransferNotExists {})
+                    asset_limits(deps.storage).save(denom.as_bytes(), &limits)?;
+                    Ok(res.add_attribute("limits", to_json_string(&limits)?))
                 }
-            })?;
-            let config_state = config(deps.storage).load()?;
-            Ok(Response::new()
-                .add_message(BankMsg::Send {
-                    to_address: receiver,
-                    amount: vec![coin],
+                None => {
+                    asset_limits(deps.storage).remove(denom.as_bytes());
+                    Ok(res.add_attribute("limits", ""))
+                }
+            }
+        }
+    }
+}
+
+fn confirm(
+    deps: DepsMut,
+    env: Env,
+    caller: &Addr,
+    transfer_id: TransferId,
+    secret: SecretKey,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, |state| state.confirm, "confirm")?;
+    let config_state = config(deps.storage).load()?;
+    let mut payouts = Payouts::default();
+    let transfer = confirm_transfer(
+        deps.storage,
+        &env,
+        &config_state,
+        caller,
+        transfer_id,
+        secret,
+        &mut payouts,
+    )?;
+    Ok(Response::new()
+        .add_submessages(payouts.into_msgs()?)
+        .add_messages(ibc::relay_secret(&transfer)?))
+}
+
+/// confirms a single transfer and adds its payments to `payouts`, the tip goes to `caller`
+fn confirm_transfer(
+    storage: &mut dyn Storage,
+    env: &Env,
+    config_state: &Config,
+    caller: &Addr,
+    transfer_id: TransferId,
+    secret: SecretKey,
+    payouts: &mut Payouts,
+) -> Result<TransferRecord, ContractError> {
+    let transfer = transfers(storage).update(&transfer_id, |t| {
+        if let Some(mut transfer) = t {
+            if try_lock(secret, transfer.hashlock) && transfer.status == TransferStatus::Pending {
+                transfer.assert_can_confirm(&env.block, caller)?;
+                transfer.secret_key = secret;
+                transfer.status = TransferStatus::Confirmed;
+                transfer.finalized_at = Some(env.block.time.seconds());
+                transfer.bounty = config_state.bounty_for(transfer.fee.as_ref());
+                Ok(transfer)
+            } else {
+                Err(ContractError::IncorrectSecret {})
+            }
+        } else {
+            Err(ContractError::TransferNotExists {})
+        }
+    })?;
+    settle_totals(storage, &transfer)?;
+    index_finalized(storage, &transfer_id, &transfer)?;
+    hashlocks(storage).update(&transfer.hashlock, |state| -> StdResult<_> {
+        Ok(HashlockState {
+            revealed: true,
+            ..state.unwrap_or_default()
+        })
+    })?;
+    match &transfer.forward {
+        Some(forward) => payouts.forwards.push(forward::forward_payout(
+            storage,
+            env,
+            transfer_id,
+            &transfer,
+            forward,
+        )?),
+        None => payouts.add(
+            &transfer,
+            transfer.receiver.to_string(),
+            transfer.coin.clone(),
+        ),
+    }
+    if let Some(fee) = transfer.platform_fee() {
+        for (to_address, share) in config_state.fee_split(fee, transfer.referrer.as_ref()) {
+            payouts.add(&transfer, to_address, share);
+        }
+    }
+    if let Some(tip) = transfer.tip.clone() {
+        payouts.add(&transfer, caller.to_string(), tip);
+    }
+    settle_premium(storage, &transfer_id, &transfer, false, payouts)?;
+    Ok(transfer)
+}
+
+/// pays the premium of a finalized transfer, forfeited to the sender if `forfeit`
+fn set_delegate(
+    deps: DepsMut,
+    info: MessageInfo,
+    transfer_id: TransferId,
+    delegate: Option<String>,
+) -> Result<Response, ContractError> {
+    let mut transfer = transfers_read(deps.storage)
+        .may_load(&transfer_id)?
+        .ok_or(ContractError::TransferNotExists)?;
+    if transfer.receiver != info.sender {
+        return Err(ContractError::Unauthorized);
+    }
+    if transfer.status != TransferStatus::Pending {
+        return Err(ContractError::TransferNotPending);
+    }
+    transfer.delegate = delegate
+        .map(|delegate| deps.api.addr_validate(&delegate))
+        .transpose()?;
+    transfers(deps.storage).save(&transfer_id, &transfer)?;
+    Ok(Response::new()
+        .add_attribute("action", "set_delegate")
+        .add_attribute("transfer_id", hex::encode(transfer_id))
+        .add_attribute(
+            "delegate",
+            transfer
+                .delegate
+                .as_ref()
+                .map(Addr::as_str)
+                .unwrap_or_default(),
+        ))
+}
+
+fn settle_premium(
+    storage: &mut dyn Storage,
+    transfer_id: &TransferId,
+    transfer: &TransferRecord,
+    forfeit: bool,
+    payouts: &mut Payouts,
+) -> StdResult<()> {
+    if let Some((to_address, coin)) = premium::settle(storage, transfer_id, transfer, forfeit)? {
+        payouts.add(transfer, to_address.into(), coin);
+    }
+    Ok(())
+}
+
+/// refunds a pending transfer with its fee and tip to the sender, whatever its timelock
+fn refund_in_full(
+    storage: &mut dyn Storage,
+    env: &Env,
+    transfer_id: TransferId,
+    payouts: &mut Payouts,
+) -> Result<TransferRecord, ContractError> {
+    let transfer = transfers(storage).update(&transfer_id, |t| {
+        let mut transfer = t.ok_or(ContractError::TransferNotExists)?;
+        if transfer.status != TransferStatus::Pending {
+            return Err(ContractError::TransferNotPending);
+        }
+        transfer.status = TransferStatus::Refunded;
+        transfer.finalized_at = Some(env.block.time.seconds());
+        Ok(transfer)
+    })?;
+    settle_totals(storage, &transfer)?;
+    index_finalized(storage, &transfer_id, &transfer)?;
+    let returned = std::iter::once(&transfer.coin)
+        .chain(transfer.fee.iter())
+        .chain(transfer.tip.iter());
+    for coin in returned.cloned() {
+        payouts.add(&transfer, transfer.sender.to_string(), coin);
+    }
+    settle_premium(storage, &transfer_id, &transfer, false, payouts)?;
+    Ok(transfer)
+}
+
+fn assert_not_paused(
+    storage: &dyn Storage,
+    paused: impl Fn(&PauseState) -> bool,
+    operation: &str,
+) -> Result<(), ContractError> {
+    if paused(&load_pause_state(storage)?) {
+        Err(ContractError::Paused {
+            operation: operation.into(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn refund(
+    deps: DepsMut,
+    env: Env,
+    caller: &Addr,
+    transfer_id: TransferId,
+) -> Result<Response, ContractError> {
+    let paused = load_pause_state(deps.storage)?.refund;
+    let config_state = config(deps.storage).load()?;
+    let mut payouts = Payouts::default();
+    refund_transfer(
+        deps.storage,
+        &env,
+        &config_state,
+        paused,
+        caller,
+        transfer_id,
+        &mut payouts,
+    )?;
+    Ok(Response::new().add_submessages(payouts.into_msgs()?))
+}
+
+/// refunds a single transfer and adds its payments to `payouts`, while `paused` only the
+/// sender may refund, a transfer whose premium is overdue does not wait for its timelock
+fn refund_transfer(
+    storage: &mut dyn Storage,
+    env: &Env,
+    config_state: &Config,
+    paused: bool,
+    caller: &Addr,
+    transfer_id: TransferId,
+    payouts: &mut Payouts,
+) -> Result<TransferRecord, ContractError> {
+    let premium_overdue = matches!(
+        premium::premiums_read(storage).may_load(&transfer_id)?,
+        Some(premium) if premium.is_overdue(&env.block)
+    );
+    let transfer = transfers(storage).update(&transfer_id, |t| {
+        if let Some(mut transfer) = t {
+            if paused && transfer.sender != *caller {
+                Err(ContractError::Paused {
+                    operation: "refund".into(),
                 })
-                .add_message(BankMsg::Send {
-                    to_address: config_state.platform,
-                    amount: vec![config_state.fee],
-                }))
-        }
-        ExecuteMsg::Refund(msg) => {
-            let TransferMsg {
-                sender,
-                receiver,
-                coin,
-                hashlock,
-                timelock,
-            } = msg;
-            let transfer_id = keccak256(&sender, &receiver, &coin, &hashlock, timelock);
-            transfers(deps.storage).update(&transfer_id, |t| {
-                if let Some(mut transfer) = t {
-                    if env.block.time.seconds() > timelock {
-                        transfer.status = TransferStatus::Refunded;
-                        Ok(transfer)
-                    } else {
-                        Err(ContractError::TransferLocked {})
-                    }
-                } else {
-                    Err(ContractError::TransferNotExists {})
+            } else if transfer.status != TransferStatus::Pending {
+                Err(ContractError::TransferNotPending {})
+            } else if transfer.is_expired(&env.block) || premium_overdue {
+                transfer.status = TransferStatus::Refunded;
+                transfer.finalized_at = Some(env.block.time.seconds());
+                if !transfer.refund_fee {
+                    transfer.bounty = config_state.bounty_for(transfer.fee.as_ref());
                 }
-            })?;
-            let config_state = config(deps.storage).load()?;
-            Ok(Response::new()
-                .add_message(BankMsg::Send {
-                    to_address: sender,
-                    amount: vec![coin],
+                Ok(transfer)
+            } else {
+                Err(ContractError::TransferLocked {})
+            }
+        } else {
+            Err(ContractError::TransferNotExists {})
+        }
+    })?;
+    settle_totals(storage, &transfer)?;
+    index_finalized(storage, &transfer_id, &transfer)?;
+    payouts.add(
+        &transfer,
+        transfer.sender.to_string(),
+        transfer.coin.clone(),
+    );
+    if let Some(tip) = transfer.tip.clone() {
+        payouts.add(&transfer, transfer.sender.to_string(), tip);
+    }
+    if transfer.refund_fee {
+        if let Some(fee) = transfer.fee.clone() {
+            payouts.add(&transfer, transfer.sender.to_string(), fee);
+        }
+    } else if let Some(fee) = transfer.platform_fee() {
+        for (to_address, share) in config_state.fee_split(fee, transfer.referrer.as_ref()) {
+            payouts.add(&transfer, to_address, share);
+        }
+    }
+    settle_premium(storage, &transfer_id, &transfer, true, payouts)?;
+    Ok(transfer)
+}
+
+fn cleanup(
+    deps: DepsMut,
+    env: Env,
+    caller: Addr,
+    ids: Option<Vec<TransferId>>,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let config_state = config(deps.storage).load()?;
+    let now = env.block.time.seconds();
+    let limit = limit.unwrap_or(MAX_CLEANUP_LIMIT).min(MAX_CLEANUP_LIMIT) as usize;
+    let removable =
+        |record: &TransferRecord| record.is_removable(now, config_state.cleanup_retention);
+
+    // ids not removable yet are skipped, so racing cleanups do not fail each other, and repeated
+    // ids are removed once so the bounty is paid once
+    let records = match ids {
+        Some(mut ids) => {
+            ids.sort_unstable();
+            ids.dedup();
+            ids.into_iter()
+                .filter_map(|id| {
+                    transfers_read(deps.storage)
+                        .may_load(&id)
+                        .map(|record| record.filter(removable).map(|record| (id.to_vec(), record)))
+                        .transpose()
+                })
+                .take(limit)
+                .collect::<StdResult<Vec<_>>>()?
+        }
+        // only transfers finalized long enough ago are visited, so the scan stays within `limit`
+        None => match now.checked_sub(config_state.cleanup_retention) {
+            Some(latest) => finalized_read(deps.storage)
+                .range(
+                    None,
+                    Some(&latest.saturating_add(1).to_be_bytes()),
+                    Order::Ascending,
+                )
+                .take(limit)
+                .map(|item| {
+                    let id = item?.0.split_off(8);
+                    let record = transfers_read(deps.storage).load(&id)?;
+                    Ok((id, record))
                 })
-                .add_message(BankMsg::Send {
-                    to_address: config_state.platform.to_string(),
-                    amount: vec![config_state.fee],
-                }))
+                .collect::<StdResult<Vec<_>>>()?,
+            None => vec![],
+        },
+    };
+
+    let mut res = Response::new()
+        .add_attribute("action", "cleanup")
+        .add_attribute("removed", records.len().to_string());
+    for (id, record) in records {
+        transfers(deps.storage).remove(&id);
+        finalized(deps.storage)
+            .remove(&finalized_key(record.finalized_at.unwrap_or_default(), &id));
+        premium::premiums(deps.storage).remove(&id);
+        // the removed record stays available to indexers through the event
+        res = res.add_event(
+            Event::new("transfer_removed")
+                .add_attribute("transfer_id", hex::encode(&id))
+                .add_attribute("sender", record.sender.as_str())
+                .add_attribute("receiver", record.receiver.as_str())
+                .add_attribute("coin", record.coin.to_string())
+                .add_attribute("hashlock", hex::encode(record.hashlock))
+                .add_attribute("timelock", record.timelock.to_string())
+                .add_attribute("secret_key", hex::encode(record.secret_key))
+                .add_attribute("status", format!("{:?}", record.status))
+                .add_attribute(
+                    "finalized_at",
+                    record
+                        .finalized_at
+                        .map(|t| t.to_string())
+                        .unwrap_or_default(),
+                ),
+        );
+        if let Some(bounty) = record.bounty.clone() {
+            release(deps.storage, &bounty)?;
+            res = res.add_message(record.send(caller.to_string(), bounty)?);
         }
     }
+    Ok(res)
 }
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: TransferMsg) -> StdResult<Binary> {
+/// any excess of `sent` goes back to `payer`
+fn fund(
+    mut deps: DepsMut,
+    env: Env,
+    payer: &Addr,
+    sent: &[Coin],
+    asset: Asset,
+    msg: TransferMsg,
+    leg: Option<CrossChainLeg>,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, |state| state.fund, "fund")?;
+    let config_state = config(deps.storage).load()?;
+    let (transfer_id, record) =
+        fund_transfer(deps.branch(), &env, &config_state, sent, asset, msg, leg)?;
+    let mut remaining = sent.to_vec();
+    deduct_payment(&mut remaining, &record);
+    let mut payouts = Payouts::default();
+    for coin in excess_payment(&config_state, remaining)? {
+        payouts.add(&record, payer.to_string(), coin);
+    }
+    Ok(Response::new()
+        .add_submessages(payouts.into_msgs()?)
+        .add_events(
+            record
+                .leg
+                .as_ref()
+                .map(|leg| leg.event(&transfer_id, &record)),
+        )
+        .set_data(transfer_id))
+}
+
+/// opens a single transfer paid from `sent`
+fn fund_transfer(
+    deps: DepsMut,
+    env: &Env,
+    config_state: &Config,
+    sent: &[Coin],
+    asset: Asset,
+    msg: TransferMsg,
+    leg: Option<CrossChainLeg>,
+) -> Result<(TransferId, TransferRecord), ContractError> {
+    let transfer_id = msg.transfer_id();
+    // the fields left out of the id must not overwrite the record of a funded transfer
+    if transfers_read(deps.storage)
+        .may_load(&transfer_id)?
+        .is_some()
+    {
+        return Err(ContractError::TransferExists);
+    }
     let TransferMsg {
         sender,
         receiver,
         coin,
         hashlock,
         timelock,
+        timelock_kind,
+        tip,
+        allow_hashlock_reuse,
+        forward,
+        referrer,
+        exclusive_window,
+        delegate,
+        premium,
     } = msg;
-    let transfer_id = keccak256(&sender, &receiver, &coin, &hashlock, timelock);
-    let transfer = transfers_read(deps.storage).load(&transfer_id)?;
-    to_binary(&transfer)
+    let fee = config_state.fee_for(&coin);
+    let extra_coins: Vec<_> = fee.iter().chain(tip.iter()).collect();
+    assert_sent_sufficient_coin(sent, &coin, &extra_coins)?;
+    config_state.assert_timelock(&env.block, &timelock_kind, timelock)?;
+    if let Some(forward) = &forward {
+        forward.validate(&asset, config_state.max_timelock)?;
+    }
+    if let Some(premium) = &premium {
+        premium.validate(&asset, &coin, config_state.max_timelock)?;
+    }
+    let totals = locked_totals(deps.storage, config_state, &asset, &coin)?;
+    let hashlock_state = tracked_hashlock(deps.storage, &hashlock, allow_hashlock_reuse)?;
+    let record = TransferRecord {
+        sender: deps.api.addr_validate(&sender)?,
+        receiver: deps.api.addr_validate(&receiver)?,
+        coin,
+        asset,
+        fee,
+        refund_fee: config_state.refund_fee,
+        hashlock,
+        timelock,
+        timelock_kind,
+        secret_key: [0; 32],
+        status: TransferStatus::Pending,
+        finalized_at: None,
+        bounty: None,
+        leg,
+        ibc: None,
+        tip,
+        forward,
+        referrer: referrer
+            .map(|referrer| deps.api.addr_validate(&referrer))
+            .transpose()?,
+        exclusive_until: exclusive_window
+            .map(|window| env.block.time.seconds().saturating_add(window)),
+        delegate: delegate
+            .map(|delegate| deps.api.addr_validate(&delegate))
+            .transpose()?,
+    };
+
+    // every check is done, a failed item of a best-effort batch leaves nothing behind
+    asset_totals(deps.storage).save(record.coin.denom.as_bytes(), &totals)?;
+    hashlocks(deps.storage).save(&record.hashlock, &hashlock_state)?;
+    for coin in record.fee.iter().chain(record.tip.iter()) {
+        reserve(deps.storage, &record.asset_for(coin), coin)?;
+    }
+    transfers(deps.storage).save(&transfer_id, &record)?;
+    if let Some(premium) = premium {
+        premium::open(deps.storage, env, &transfer_id, premium)?;
+    }
+    Ok((transfer_id, record))
 }
 
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Transfer(msg) => {
+            to_json_binary(&transfers_read(deps.storage).load(&msg.transfer_id())?)
+        }
+        QueryMsg::TransferById { id } => to_json_binary(&transfers_read(deps.storage).load(&id)?),
+        QueryMsg::Config {} => to_json_binary(&config_read(deps.storage).load()?),
+        QueryMsg::PauseState {} => to_json_binary(&load_pause_state(deps.storage)?),
+        QueryMsg::Liquidity { channel, maker } => to_json_binary(
+            &ibc::liquidity_read(deps.storage, &channel)
+                .may_load(maker.as_bytes())?
+                .unwrap_or_default(),
+        ),
+        QueryMsg::Hashlock { hashlock } => to_json_binary(
+            &hashlocks_read(deps.storage)
+                .may_load(&hashlock)?
+                .unwrap_or_default(),
+        ),
+        QueryMsg::AssetLimits { denom } => {
+            to_json_binary(&asset_limits_read(deps.storage).may_load(denom.as_bytes())?)
+        }
+        QueryMsg::AssetList {} => {
+            let assets = asset_limits_read(deps.storage)
+                .range(None, None, Order::Ascending)
+                .map(|item| {
+                    let (denom, limits) = item?;
+                    Ok((String::from_utf8(denom)?, limits))
+                })
+                .collect::<StdResult<Vec<_>>>()?;
+            to_json_binary(&assets)
+        }
+        QueryMsg::Claimable { address } => to_json_binary(
+            &forward::claimable_read(deps.storage)
+                .may_load(address.as_bytes())?
+                .unwrap_or_default(),
+        ),
+        QueryMsg::Solvency {} => {
+            let denoms = asset_totals_read(deps.storage)
+                .range(None, None, Order::Ascending)
+                .map(|item| Ok(String::from_utf8(item?.0)?))
+                .collect::<StdResult<Vec<_>>>()?;
+            let solvency = denoms
+                .into_iter()
+                .map(|denom| solvency(deps, &env, denom))
+                .collect::<StdResult<Vec<_>>>()?;
+            to_json_binary(&solvency)
+        }
+        QueryMsg::Premium { id } => {
+            to_json_binary(&premium::premiums_read(deps.storage).may_load(&id)?)
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn keccak256(
     sender: &String,
     bridge: &String,
     coin: &Coin,
     hashlock: &[u8; 32],
     timelock: u64,
+    timelock_kind: &TimelockKind,
+    tip: Option<&Coin>,
+    forward: Option<&Forward>,
+    delegate: Option<&String>,
 ) -> [u8; 32] {
     let mut hasher = Sha3_256::new();
     hasher.update(sender.as_bytes());
@@ -270,7 +2287,26 @@ fn keccak256(
     hasher.update(coin.denom.as_bytes());
     hasher.update(hashlock);
     hasher.update(timelock.to_be_bytes());
+    // timestamp locks keep the ids of the transfers created before height locks
+    if timelock_kind == &TimelockKind::Height {
+        hasher.update(b"height");
+    }
+    if let Some(tip) = tip {
+        hasher.update(b"tip");
+        hasher.update(tip.amount.to_be_bytes());
+        hasher.update(tip.denom.as_bytes());
+    }
+    if let Some(forward) = forward {
+        hasher.update(b"forward");
+        hasher.update(forward.channel.as_bytes());
+        hasher.update(forward.remote_address.as_bytes());
+        hasher.update(forward.timeout.to_be_bytes());
+    }
+    if let Some(delegate) = delegate {
+        hasher.update(b"delegate");
+        hasher.update(delegate.as_bytes());
+    }
     let result = hasher.finalize();
-    let out: [u8; 32] = result.try_into().unwrap();
+    let out: [u8; 32] = result.into();
     out
 }
@@ -0,0 +1,199 @@
+//! Storage layouts of previous contract versions and the migrations from them.
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{from_json, Addr, Coin, Order, StdResult, Storage, Uint128};
+use cosmwasm_storage::{bucket_read, singleton_read, ReadonlyPrefixedStorage};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utils::{HashLock, SecretKey};
+
+use crate::forward::claimable_read;
+use crate::ibc::{Liquidity, LIQUIDITY_KEY};
+use crate::premium::{premiums_read, PremiumStatus};
+use crate::{
+    asset_totals, asset_totals_read, config, hashlocks, index_finalized, transfers, transfers_read,
+    Asset, AssetTotals, Config, FeeRate, FeeRule, HashlockState, TimelockKind, TransferRecord,
+    TransferStatus, CONFIG_KEY, DEFAULT_CLEANUP_RETENTION, DEFAULT_MAX_HEIGHT_LOCK,
+    DEFAULT_MAX_TIMELOCK, DEFAULT_MIN_HEIGHT_LOCK, DEFAULT_MIN_TIMELOCK, TRANSFER_KEY,
+};
+
+/// The config of 0.1.0, a flat fee in a single denom
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigV0 {
+    pub platform: String,
+    pub fee: Coin,
+}
+
+/// The transfer record of 0.1.0, native coins only and the fee taken from the config
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct TransferRecordV0 {
+    pub sender: Addr,
+    pub receiver: Addr,
+    pub coin: Coin,
+    pub hashlock: HashLock,
+    pub timelock: u64,
+    pub secret_key: SecretKey,
+    pub status: TransferStatus,
+}
+
+/// 0.1.0 did not record a contract version, so the contract is recognized by its config layout
+pub fn is_v0(storage: &dyn Storage) -> bool {
+    singleton_read::<ConfigV0>(storage, CONFIG_KEY)
+        .load()
+        .is_ok()
+}
+
+/// rewrites the 0.1.0 config and every transfer record into the current layout
+pub fn from_v0(storage: &mut dyn Storage, owner: Addr) -> StdResult<usize> {
+    let ConfigV0 { platform, fee } = singleton_read(storage, CONFIG_KEY).load()?;
+
+    // 0.1.0 only accepted transfers in the fee denom and always paid the fee to the platform
+    config(storage).save(&Config {
+        owner: Some(owner),
+        pending_owner: None,
+        platform,
+        fees: vec![FeeRule {
+            denom: fee.denom.clone(),
+            rate: FeeRate::Flat(fee.clone()),
+        }],
+        refund_fee: false,
+        min_timelock: DEFAULT_MIN_TIMELOCK,
+        max_timelock: DEFAULT_MAX_TIMELOCK,
+        min_height_lock: DEFAULT_MIN_HEIGHT_LOCK,
+        max_height_lock: DEFAULT_MAX_HEIGHT_LOCK,
+        cleanup_retention: DEFAULT_CLEANUP_RETENTION,
+        cleanup_bounty_bps: 0,
+        strict_payment: false,
+        restrict_assets: false,
+        fee_recipients: vec![],
+        referrer_share_bps: 0,
+    })?;
+
+    let records = bucket_read::<TransferRecordV0>(storage, TRANSFER_KEY)
+        .range(None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let migrated = records.len();
+    for (id, record) in records {
+        hashlocks(storage).update(&record.hashlock, |state| -> StdResult<_> {
+            let state = state.unwrap_or_default();
+            Ok(HashlockState {
+                transfers: state.transfers + 1,
+                revealed: state.revealed || record.status == TransferStatus::Confirmed,
+            })
+        })?;
+        let TransferRecordV0 {
+            sender,
+            receiver,
+            coin,
+            hashlock,
+            timelock,
+            secret_key,
+            status,
+        } = record;
+        transfers(storage).save(
+            &id,
+            &TransferRecord {
+                sender,
+                receiver,
+                coin,
+                asset: Asset::Native,
+                fee: Some(fee.clone()),
+                refund_fee: false,
+                hashlock,
+                timelock,
+                timelock_kind: TimelockKind::Timestamp,
+                secret_key,
+                status,
+                finalized_at: None,
+                bounty: None,
+                leg: None,
+                ibc: None,
+                tip: None,
+                forward: None,
+                referrer: None,
+                exclusive_until: None,
+                delegate: None,
+            },
+        )?;
+    }
+    Ok(migrated)
+}
+
+/// lists every finalized transfer in the finalized index, including those finalized before
+/// the index existed
+pub fn rebuild_finalized(storage: &mut dyn Storage) -> StdResult<()> {
+    let records = transfers_read(storage)
+        .range(None, None, Order::Ascending)
+        .filter(|item| {
+            item.as_ref()
+                .map_or(true, |(_, record)| record.status != TransferStatus::Pending)
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    for (id, record) in records {
+        index_finalized(storage, &id, &record)?;
+    }
+    Ok(())
+}
+
+/// recomputes the locked and reserved totals of every denom from the stored transfers,
+/// claimable balances and maker liquidity, the confirmed and refunded history is kept
+pub fn rebuild_totals(storage: &mut dyn Storage) -> StdResult<()> {
+    let mut totals = asset_totals_read(storage)
+        .range(None, None, Order::Ascending)
+        .map(|item| {
+            let (denom, totals) = item?;
+            let totals = AssetTotals {
+                locked: Uint128::zero(),
+                reserved: Uint128::zero(),
+                ..totals
+            };
+            Ok((String::from_utf8(denom)?, totals))
+        })
+        .collect::<StdResult<BTreeMap<_, _>>>()?;
+    let mut count = |asset: &Asset, coin: &Coin, locked: bool| {
+        let entry = totals.entry(coin.denom.clone()).or_default();
+        entry.asset = asset.clone();
+        if locked {
+            entry.locked += coin.amount;
+        } else {
+            entry.reserved += coin.amount;
+        }
+    };
+
+    for item in transfers_read(storage).range(None, None, Order::Ascending) {
+        let (_, record) = item?;
+        if record.status == TransferStatus::Pending {
+            count(&record.asset, &record.coin, true);
+            for coin in record.fee.iter().chain(record.tip.iter()) {
+                count(&record.asset_for(coin), coin, false);
+            }
+        } else if let Some(bounty) = &record.bounty {
+            count(&record.asset_for(bounty), bounty, false);
+        }
+    }
+    for item in claimable_read(storage).range(None, None, Order::Ascending) {
+        for coin in item?.1 {
+            count(&Asset::Native, &coin, false);
+        }
+    }
+    for item in premiums_read(storage).range(None, None, Order::Ascending) {
+        let (_, premium) = item?;
+        if premium.status == PremiumStatus::Deposited {
+            count(&Asset::Native, &premium.coin, false);
+        }
+    }
+    // the liquidity of every channel, closed ones included
+    for (_, value) in
+        ReadonlyPrefixedStorage::new(storage, LIQUIDITY_KEY).range(None, None, Order::Ascending)
+    {
+        let liquidity: Liquidity = from_json(&value)?;
+        for coin in liquidity.funds {
+            count(&Asset::Native, &coin, false);
+        }
+    }
+
+    for (denom, totals) in totals {
+        asset_totals(storage).save(denom.as_bytes(), &totals)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,193 @@
+//! Premiums holding the counterparty of a swap to it.
+//!
+//! The sender of a transfer can ask for a premium, deposited by the receiver, the counterparty,
+//! within a window after funding. The premium goes back to the depositor when the transfer is confirmed and to
+//! the sender when the transfer expires unconfirmed. Left unpaid past its window, the sender does
+//! not need to wait for the timelock to refund.
+use cosmwasm_std::{
+    Addr, BankMsg, BlockInfo, Coin, DepsMut, Env, MessageInfo, Response, StdResult, Storage,
+};
+use cosmwasm_storage::{bucket, bucket_read, Bucket, ReadonlyBucket};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assert_not_paused, assert_sent_sufficient_coin, config, excess_payment, release, reserve,
+    transfers_read, Asset, ContractError, TransferId, TransferRecord, TransferStatus,
+};
+
+pub static PREMIUM_KEY: &[u8] = b"premiums";
+
+/// The premium the sender asks from the counterparty
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct PremiumTerms {
+    /// a native coin, forfeited to the sender when the transfer expires unconfirmed
+    pub coin: Coin,
+    /// the seconds after funding the premium is due, at most the longest timelock
+    pub deposit_window: u64,
+}
+
+impl PremiumTerms {
+    /// premiums are paid in native coins, a token transfer cannot share their denom
+    pub fn validate(
+        &self,
+        asset: &Asset,
+        coin: &Coin,
+        max_window: u64,
+    ) -> Result<(), ContractError> {
+        if self.coin.amount.is_zero()
+            || !(1..=max_window).contains(&self.deposit_window)
+            || (*asset != Asset::Native && self.coin.denom == coin.denom)
+        {
+            return Err(ContractError::InvalidPremium);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PremiumStatus {
+    Awaiting,
+    Deposited,
+    /// back to the depositor, the transfer was confirmed or refunded by governance
+    Returned,
+    /// paid to the sender, the transfer expired unconfirmed
+    Forfeited,
+    /// never deposited before the transfer was finalized
+    Lapsed,
+}
+
+/// The premium linked to a transfer, by transfer id
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Premium {
+    pub coin: Coin,
+    /// the block time the deposit is due, in seconds
+    pub due: u64,
+    pub depositor: Option<Addr>,
+    pub status: PremiumStatus,
+}
+
+impl Premium {
+    /// whether the deposit is still missing past its window
+    pub fn is_overdue(&self, block: &BlockInfo) -> bool {
+        self.status == PremiumStatus::Awaiting && block.time.seconds() > self.due
+    }
+}
+
+pub fn premiums(storage: &mut dyn Storage) -> Bucket<'_, Premium> {
+    bucket(storage, PREMIUM_KEY)
+}
+
+pub fn premiums_read(storage: &dyn Storage) -> ReadonlyBucket<'_, Premium> {
+    bucket_read(storage, PREMIUM_KEY)
+}
+
+/// links the premium asked by `terms` to a newly funded transfer
+pub fn open(
+    storage: &mut dyn Storage,
+    env: &Env,
+    transfer_id: &TransferId,
+    terms: PremiumTerms,
+) -> StdResult<()> {
+    premiums(storage).save(
+        transfer_id,
+        &Premium {
+            coin: terms.coin,
+            due: env
+                .block
+                .time
+                .seconds()
+                .saturating_add(terms.deposit_window),
+            depositor: None,
+            status: PremiumStatus::Awaiting,
+        },
+    )
+}
+
+/// deposits the premium of a pending transfer, any excess goes back to the caller
+pub fn deposit(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    transfer_id: TransferId,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, |state| state.fund, "fund")?;
+    let transfer = transfers_read(deps.storage)
+        .may_load(&transfer_id)?
+        .ok_or(ContractError::TransferNotExists)?;
+    // the receiver is the one held to the swap, a stranger could hold off the early refund
+    if transfer.receiver != info.sender {
+        return Err(ContractError::Unauthorized);
+    }
+    if transfer.status != TransferStatus::Pending {
+        return Err(ContractError::TransferNotPending);
+    }
+    let mut premium = premiums_read(deps.storage)
+        .may_load(&transfer_id)?
+        .filter(|premium| premium.status == PremiumStatus::Awaiting)
+        .ok_or(ContractError::NoPremiumDue)?;
+    if premium.is_overdue(&env.block) {
+        return Err(ContractError::PremiumOverdue { due: premium.due });
+    }
+    assert_sent_sufficient_coin(&info.funds, &premium.coin, &[])?;
+
+    let mut remaining = info.funds;
+    if let Some(fund) = remaining
+        .iter_mut()
+        .find(|fund| fund.denom == premium.coin.denom)
+    {
+        fund.amount -= premium.coin.amount;
+    }
+    let excess = excess_payment(&config(deps.storage).load()?, remaining)?;
+    reserve(deps.storage, &Asset::Native, &premium.coin)?;
+    premium.depositor = Some(info.sender.clone());
+    premium.status = PremiumStatus::Deposited;
+    premiums(deps.storage).save(&transfer_id, &premium)?;
+
+    let mut res = Response::new()
+        .add_attribute("action", "deposit_premium")
+        .add_attribute("transfer_id", hex::encode(transfer_id))
+        .add_attribute("depositor", info.sender.as_str())
+        .add_attribute("premium", premium.coin.to_string());
+    if !excess.is_empty() {
+        res = res.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: excess,
+        });
+    }
+    Ok(res)
+}
+
+/// resolves the premium of a finalized transfer, a deposit is forfeited to the sender if
+/// `forfeit`, otherwise returned, and the payment is returned with its recipient
+pub fn settle(
+    storage: &mut dyn Storage,
+    transfer_id: &TransferId,
+    transfer: &TransferRecord,
+    forfeit: bool,
+) -> StdResult<Option<(Addr, Coin)>> {
+    let mut premium = match premiums_read(storage).may_load(transfer_id)? {
+        Some(premium) => premium,
+        None => return Ok(None),
+    };
+    let payment = match (&premium.status, premium.depositor.clone()) {
+        (PremiumStatus::Deposited, Some(depositor)) => {
+            release(storage, &premium.coin)?;
+            if forfeit {
+                premium.status = PremiumStatus::Forfeited;
+                Some((transfer.sender.clone(), premium.coin.clone()))
+            } else {
+                premium.status = PremiumStatus::Returned;
+                Some((depositor, premium.coin.clone()))
+            }
+        }
+        (PremiumStatus::Awaiting, _) => {
+            premium.status = PremiumStatus::Lapsed;
+            None
+        }
+        _ => return Ok(None),
+    };
+    premiums(storage).save(transfer_id, &premium)?;
+    Ok(payment)
+}
@@ -1,15 +1,23 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
-    use std::time::SystemTime;
-
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{coin, from_binary, Deps};
+    use cosmwasm_std::storage_keys::to_length_prefixed;
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_ibc_channel_close_init, mock_ibc_channel_connect_ack,
+        mock_ibc_channel_open_init, mock_ibc_packet_ack, mock_ibc_packet_recv,
+        mock_ibc_packet_timeout, mock_info, MockApi, MockQuerier, MockStorage,
+    };
+    use cosmwasm_std::{
+        attr, coin, from_json, Deps, DepsMut, IbcAcknowledgement, IbcMsg, IbcOrder, OwnedDeps,
+        Reply, ReplyOn, SubMsgResponse, SubMsgResult,
+    };
 
+    use crate::ibc::*;
     use crate::*;
 
     fn assert_query(deps: Deps, msg: TransferMsg, secret_key: SecretKey, status: TransferStatus) {
-        let res = query(deps, mock_env(), msg.clone()).unwrap();
-        let record: TransferRecord = from_binary(&res).unwrap();
+        let res = query(deps, mock_env(), QueryMsg::Transfer(msg.clone())).unwrap();
+        let record: TransferRecord = from_json(&res).unwrap();
         assert_eq!(record.sender, msg.sender);
         assert_eq!(record.receiver, msg.receiver);
         assert_eq!(record.coin, msg.coin);
@@ -22,17 +30,19 @@ mod tests {
     fn call_fund_without_deposit() {
         let mut deps = mock_dependencies();
 
-        let timelock = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        let timelock = mock_env().block.time.seconds() + 3600;
 
         let msg = InstantiateMsg {
             platform: "platform".into(),
-            fee: coin(1, "atom"),
+            fees: vec![FeeRule {
+                denom: "atom".into(),
+                rate: FeeRate::Flat(coin(1, "atom")),
+            }],
+            refund_fee: false,
+            ..Default::default()
         };
 
-        let info = mock_info("sender", &vec![]);
+        let info = mock_info("sender", &[]);
         assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
 
         let msg = ExecuteMsg::Fund(TransferMsg {
@@ -44,8 +54,9 @@ mod tests {
                 43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
             ],
             timelock,
+            ..Default::default()
         });
-        let info = mock_info("sender", &vec![]);
+        let info = mock_info("sender", &[]);
 
         assert_eq!(
             execute(deps.as_mut(), mock_env(), info, msg),
@@ -57,17 +68,19 @@ mod tests {
     fn round_trip() {
         let mut deps = mock_dependencies();
 
-        let timelock = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        let timelock = mock_env().block.time.seconds() + 3600;
 
         let msg = InstantiateMsg {
             platform: "platform".into(),
-            fee: coin(1, "atom"),
+            fees: vec![FeeRule {
+                denom: "atom".into(),
+                rate: FeeRate::Flat(coin(1, "atom")),
+            }],
+            refund_fee: false,
+            ..Default::default()
         };
 
-        let info = mock_info("sender", &vec![]);
+        let info = mock_info("sender", &[]);
         assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
 
         let msg = ExecuteMsg::Fund(TransferMsg {
@@ -79,8 +92,9 @@ mod tests {
                 43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
             ],
             timelock,
+            ..Default::default()
         });
-        let info = mock_info("sender", &vec![coin(101, "atom")]);
+        let info = mock_info("sender", &[coin(101, "atom")]);
 
         assert!(execute(deps.as_mut(), mock_env(), info, msg).is_ok());
         let transfer_msg = TransferMsg {
@@ -92,6 +106,7 @@ mod tests {
                 43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
             ],
             timelock,
+            ..Default::default()
         };
 
         assert_query(
@@ -111,10 +126,11 @@ mod tests {
                     166, 43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
                 ],
                 timelock,
+                ..Default::default()
             },
             *b"ssssssssssssssssssssssssssssssss",
         ));
-        let info = mock_info("sender", &vec![]);
+        let info = mock_info("sender", &[]);
 
         assert!(execute(deps.as_mut(), mock_env(), info, msg).is_ok());
 
@@ -125,4 +141,1826 @@ mod tests {
             TransferStatus::Confirmed,
         );
     }
+
+    #[test]
+    fn cw20_round_trip() {
+        let mut deps = mock_dependencies();
+
+        let timelock = mock_env().block.time.seconds() + 3600;
+
+        let msg = InstantiateMsg {
+            platform: "platform".into(),
+            fees: vec![FeeRule {
+                denom: "token".into(),
+                rate: FeeRate::Flat(coin(1, "token")),
+            }],
+            refund_fee: false,
+            ..Default::default()
+        };
+
+        let info = mock_info("sender", &[]);
+        assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        let transfer_msg = TransferMsg {
+            sender: "sender".into(),
+            receiver: "receiver".into(),
+            coin: coin(100, "token"),
+            hashlock: [
+                165, 152, 132, 76, 216, 153, 182, 114, 45, 89, 20, 251, 170, 95, 204, 77, 214, 166,
+                43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
+            ],
+            timelock,
+            ..Default::default()
+        };
+        let receive = |token: &str| {
+            let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+                sender: "sender".into(),
+                amount: 101u128.into(),
+                msg: to_json_binary(&ReceiveMsg::Fund(transfer_msg.clone())).unwrap(),
+            });
+            (mock_info(token, &[]), msg)
+        };
+
+        let (info, msg) = receive("other_token");
+        assert_eq!(
+            execute(deps.as_mut(), mock_env(), info, msg),
+            Err(ContractError::TokenMismatch)
+        );
+
+        let (info, msg) = receive("token");
+        assert!(execute(deps.as_mut(), mock_env(), info, msg).is_ok());
+        assert_query(
+            deps.as_ref(),
+            transfer_msg.clone(),
+            [0; 32],
+            TransferStatus::Pending,
+        );
+
+        let msg = ExecuteMsg::Confirm((transfer_msg, *b"ssssssssssssssssssssssssssssssss"));
+        let res = execute(deps.as_mut(), mock_env(), mock_info("receiver", &[]), msg).unwrap();
+        let token = Asset::Cw20 {
+            contract: Addr::unchecked("token"),
+        };
+        assert_eq!(
+            res.messages.into_iter().map(|m| m.msg).collect::<Vec<_>>(),
+            vec![
+                token.send("receiver".into(), coin(100, "token")).unwrap(),
+                token.send("platform".into(), coin(1, "token")).unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn fee_rules() {
+        let config = Config {
+            owner: None,
+            pending_owner: None,
+            platform: "platform".into(),
+            fees: vec![
+                FeeRule {
+                    denom: "atom".into(),
+                    rate: FeeRate::Bps {
+                        bps: 30,
+                        cap: Some(50u128.into()),
+                    },
+                },
+                FeeRule {
+                    denom: "usdc".into(),
+                    rate: FeeRate::Flat(coin(2, "osmo")),
+                },
+            ],
+            refund_fee: true,
+            min_timelock: DEFAULT_MIN_TIMELOCK,
+            max_timelock: DEFAULT_MAX_TIMELOCK,
+            min_height_lock: DEFAULT_MIN_HEIGHT_LOCK,
+            max_height_lock: DEFAULT_MAX_HEIGHT_LOCK,
+            cleanup_retention: DEFAULT_CLEANUP_RETENTION,
+            cleanup_bounty_bps: 0,
+            strict_payment: false,
+            restrict_assets: false,
+            fee_recipients: vec![],
+            referrer_share_bps: 0,
+        };
+        assert_eq!(
+            config.fee_for(&coin(10_000, "atom")),
+            Some(coin(30, "atom"))
+        );
+        assert_eq!(
+            config.fee_for(&coin(100_000, "atom")),
+            Some(coin(50, "atom"))
+        );
+        assert_eq!(config.fee_for(&coin(10, "atom")), None);
+        assert_eq!(config.fee_for(&coin(10, "usdc")), Some(coin(2, "osmo")));
+        assert_eq!(config.fee_for(&coin(10, "juno")), None);
+    }
+
+    #[test]
+    fn fee_in_other_denom_returned_on_refund() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            platform: "platform".into(),
+            fees: vec![FeeRule {
+                denom: "usdc".into(),
+                rate: FeeRate::Flat(coin(2, "osmo")),
+            }],
+            refund_fee: true,
+            ..Default::default()
+        };
+        let info = mock_info("sender", &[]);
+        assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        let transfer_msg = TransferMsg {
+            sender: "sender".into(),
+            receiver: "receiver".into(),
+            coin: coin(100, "usdc"),
+            hashlock: [
+                165, 152, 132, 76, 216, 153, 182, 114, 45, 89, 20, 251, 170, 95, 204, 77, 214, 166,
+                43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
+            ],
+            timelock: mock_env().block.time.seconds() + DEFAULT_MIN_TIMELOCK,
+            ..Default::default()
+        };
+
+        let msg = ExecuteMsg::Fund(transfer_msg.clone());
+        let info = mock_info("sender", &[coin(102, "usdc")]);
+        assert_eq!(
+            execute(deps.as_mut(), mock_env(), info, msg.clone()),
+            Err(ContractError::InsufficientFundsSend)
+        );
+        let info = mock_info("sender", &[coin(100, "usdc"), coin(2, "osmo")]);
+        assert!(execute(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(DEFAULT_MIN_TIMELOCK + 1);
+        let msg = ExecuteMsg::Refund(transfer_msg);
+        let res = execute(deps.as_mut(), env, mock_info("sender", &[]), msg).unwrap();
+        // both denoms go back in a single bank message
+        assert_eq!(
+            res.messages.into_iter().map(|m| m.msg).collect::<Vec<_>>(),
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "sender".into(),
+                amount: vec![coin(100, "usdc"), coin(2, "osmo")],
+            })]
+        );
+    }
+
+    #[test]
+    fn two_step_ownership() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            platform: "platform".into(),
+            fees: vec![],
+            refund_fee: false,
+            ..Default::default()
+        };
+        let info = mock_info("owner", &[]);
+        assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        let update = ExecuteMsg::UpdateConfig {
+            platform: Some("new_platform".into()),
+            fees: None,
+            refund_fee: Some(true),
+            min_timelock: None,
+            max_timelock: None,
+            min_height_lock: None,
+            max_height_lock: None,
+            cleanup_retention: None,
+            cleanup_bounty_bps: None,
+            strict_payment: None,
+            restrict_assets: None,
+            fee_recipients: None,
+            referrer_share_bps: None,
+        };
+        assert_eq!(
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("other", &[]),
+                update.clone()
+            ),
+            Err(ContractError::Unauthorized)
+        );
+        let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), update).unwrap();
+        assert_eq!(res.attributes.len(), 3);
+        let config_state = config_read(deps.as_ref().storage).load().unwrap();
+        assert_eq!(config_state.platform, "new_platform");
+        assert!(config_state.refund_fee);
+
+        let msg = ExecuteMsg::ProposeOwner {
+            owner: "new_owner".into(),
+        };
+        assert!(execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).is_ok());
+        assert_eq!(
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("other", &[]),
+                ExecuteMsg::AcceptOwner {}
+            ),
+            Err(ContractError::Unauthorized)
+        );
+        assert!(execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("new_owner", &[]),
+            ExecuteMsg::AcceptOwner {}
+        )
+        .is_ok());
+        let config_state = config_read(deps.as_ref().storage).load().unwrap();
+        assert_eq!(config_state.owner, Some(Addr::unchecked("new_owner")));
+        assert_eq!(config_state.pending_owner, None);
+
+        let msg = ExecuteMsg::RenounceOwnership {};
+        assert_eq!(
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("owner", &[]),
+                msg.clone()
+            ),
+            Err(ContractError::Unauthorized)
+        );
+        assert!(execute(deps.as_mut(), mock_env(), mock_info("new_owner", &[]), msg).is_ok());
+        assert_eq!(
+            config_read(deps.as_ref().storage).load().unwrap().owner,
+            None
+        );
+    }
+
+    #[test]
+    fn migrate_from_v0_snapshot() {
+        let mut deps = mock_dependencies();
+
+        let transfer_msg = TransferMsg {
+            sender: "sender".into(),
+            receiver: "receiver".into(),
+            coin: coin(100, "atom"),
+            hashlock: [
+                165, 152, 132, 76, 216, 153, 182, 114, 45, 89, 20, 251, 170, 95, 204, 77, 214, 166,
+                43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
+            ],
+            timelock: 1571797419,
+            ..Default::default()
+        };
+        let transfer_id = transfer_msg.transfer_id();
+
+        // state written by 0.1.0, which had no contract version
+        deps.storage.set(
+            &to_length_prefixed(CONFIG_KEY),
+            br#"{"platform":"platform","fee":{"denom":"atom","amount":"1"}}"#,
+        );
+        let mut key = to_length_prefixed(TRANSFER_KEY);
+        key.extend_from_slice(&transfer_id);
+        deps.storage.set(
+            &key,
+            br#"{"sender":"sender","receiver":"receiver","coin":{"denom":"atom","amount":"100"},"hashlock":[165,152,132,76,216,153,182,114,45,89,20,251,170,95,204,77,214,166,43,58,171,243,206,181,109,46,63,177,197,13,234,154],"timelock":1571797419,"secret_key":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],"status":"Pending"}"#,
+        );
+
+        assert_eq!(
+            migrate(deps.as_mut(), mock_env(), MigrateMsg { owner: None }),
+            Err(ContractError::MigrateOwnerMissing)
+        );
+        let msg = MigrateMsg {
+            owner: Some("owner".into()),
+        };
+        let res = migrate(deps.as_mut(), mock_env(), msg).unwrap();
+        assert!(res.attributes.contains(&attr("migrated_transfers", "1")));
+
+        let config_state = config_read(deps.as_ref().storage).load().unwrap();
+        assert_eq!(config_state.owner, Some(Addr::unchecked("owner")));
+        assert_eq!(
+            config_state.fee_for(&coin(100, "atom")),
+            Some(coin(1, "atom"))
+        );
+        let record = transfers_read(deps.as_ref().storage)
+            .load(&transfer_id)
+            .unwrap();
+        assert_eq!(record.asset, Asset::Native);
+        assert_eq!(record.fee, Some(coin(1, "atom")));
+        let state = hashlocks_read(deps.as_ref().storage)
+            .load(&record.hashlock)
+            .unwrap();
+        assert_eq!(state.transfers, 1);
+        let totals = asset_totals_read(deps.as_ref().storage)
+            .load(b"atom")
+            .unwrap();
+        assert_eq!(totals.locked, Uint128::new(100));
+        assert_eq!(totals.reserved, Uint128::new(1));
+        assert_eq!(
+            cw2::get_contract_version(deps.as_ref().storage)
+                .unwrap()
+                .version,
+            CONTRACT_VERSION
+        );
+        assert_query(
+            deps.as_ref(),
+            transfer_msg.clone(),
+            [0; 32],
+            TransferStatus::Pending,
+        );
+
+        // the migrated transfer still pays out the fee it was funded with
+        let msg = ExecuteMsg::Confirm((transfer_msg, *b"ssssssssssssssssssssssssssssssss"));
+        let res = execute(deps.as_mut(), mock_env(), mock_info("receiver", &[]), msg).unwrap();
+        assert_eq!(
+            res.messages.into_iter().map(|m| m.msg).collect::<Vec<_>>(),
+            vec![
+                Asset::Native
+                    .send("receiver".into(), coin(100, "atom"))
+                    .unwrap(),
+                Asset::Native
+                    .send("platform".into(), coin(1, "atom"))
+                    .unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn migrate_version_guard() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            platform: "platform".into(),
+            fees: vec![],
+            refund_fee: false,
+            ..Default::default()
+        };
+        let info = mock_info("owner", &[]);
+        assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
+        assert!(migrate(deps.as_mut(), mock_env(), MigrateMsg { owner: None }).is_ok());
+
+        cw2::set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "99.0.0").unwrap();
+        assert_eq!(
+            migrate(deps.as_mut(), mock_env(), MigrateMsg { owner: None }),
+            Err(ContractError::MigrateDowngrade {
+                from: "99.0.0".into(),
+                to: CONTRACT_VERSION.into()
+            })
+        );
+
+        cw2::set_contract_version(deps.as_mut().storage, "crates.io:other", "0.1.0").unwrap();
+        assert_eq!(
+            migrate(deps.as_mut(), mock_env(), MigrateMsg { owner: None }),
+            Err(ContractError::MigrateContractMismatch {
+                name: "crates.io:other".into()
+            })
+        );
+    }
+
+    #[test]
+    fn confirm_and_refund_by_id() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            platform: "platform".into(),
+            fees: vec![],
+            refund_fee: false,
+            ..Default::default()
+        };
+        let info = mock_info("sender", &[]);
+        assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        let fund = |deps: DepsMut, hashlock: HashLock, timelock: u64| {
+            let msg = ExecuteMsg::Fund(TransferMsg {
+                sender: "sender".into(),
+                receiver: "receiver".into(),
+                coin: coin(100, "atom"),
+                hashlock,
+                timelock,
+                ..Default::default()
+            });
+            let info = mock_info("sender", &[coin(100, "atom")]);
+            let res = execute(deps, mock_env(), info, msg).unwrap();
+            let id: TransferId = res.data.unwrap().to_vec().try_into().unwrap();
+            id
+        };
+        let now = mock_env().block.time.seconds();
+
+        let hashlock = [
+            165, 152, 132, 76, 216, 153, 182, 114, 45, 89, 20, 251, 170, 95, 204, 77, 214, 166, 43,
+            58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
+        ];
+        let id = fund(deps.as_mut(), hashlock, now + 610);
+        let msg = ExecuteMsg::ConfirmById {
+            id,
+            secret: [0; 32],
+        };
+        assert_eq!(
+            execute(deps.as_mut(), mock_env(), mock_info("receiver", &[]), msg),
+            Err(ContractError::IncorrectSecret)
+        );
+        let msg = ExecuteMsg::ConfirmById {
+            id,
+            secret: *b"ssssssssssssssssssssssssssssssss",
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("receiver", &[]), msg).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            Asset::Native
+                .send("receiver".into(), coin(100, "atom"))
+                .unwrap()
+        );
+
+        // a confirmed transfer is never refunded
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(611);
+        assert_eq!(
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info("sender", &[]),
+                ExecuteMsg::RefundById { id }
+            ),
+            Err(ContractError::TransferNotPending)
+        );
+
+        let id = fund(deps.as_mut(), [1; 32], now + 605);
+        let record: TransferRecord =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::TransferById { id }).unwrap())
+                .unwrap();
+        assert_eq!(record.status, TransferStatus::Pending);
+        assert_eq!(
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("sender", &[]),
+                ExecuteMsg::RefundById { id }
+            ),
+            Err(ContractError::TransferLocked)
+        );
+        let res = execute(
+            deps.as_mut(),
+            env,
+            mock_info("sender", &[]),
+            ExecuteMsg::RefundById { id },
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            Asset::Native
+                .send("sender".into(), coin(100, "atom"))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn cleanup_after_retention() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            platform: "platform".into(),
+            fees: vec![FeeRule {
+                denom: "atom".into(),
+                rate: FeeRate::Flat(coin(10, "atom")),
+            }],
+            cleanup_retention: Some(3600),
+            cleanup_bounty_bps: Some(2_000),
+            ..Default::default()
+        };
+        let info = mock_info("owner", &[]);
+        assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        let now = mock_env().block.time.seconds();
+        let fund = |deps: DepsMut, timelock: u64| {
+            let msg = ExecuteMsg::Fund(TransferMsg {
+                sender: "sender".into(),
+                receiver: "receiver".into(),
+                coin: coin(100, "atom"),
+                hashlock: [
+                    165, 152, 132, 76, 216, 153, 182, 114, 45, 89, 20, 251, 170, 95, 204, 77, 214,
+                    166, 43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
+                ],
+                timelock,
+                allow_hashlock_reuse: true,
+                ..Default::default()
+            });
+            let info = mock_info("sender", &[coin(110, "atom")]);
+            let res = execute(deps, mock_env(), info, msg).unwrap();
+            let id: TransferId = res.data.unwrap().to_vec().try_into().unwrap();
+            id
+        };
+        let confirmed = fund(deps.as_mut(), now + 3600);
+        let pending = fund(deps.as_mut(), now + 3601);
+
+        // the platform fee is paid without the bounty kept for the cleanup
+        let msg = ExecuteMsg::ConfirmById {
+            id: confirmed,
+            secret: *b"ssssssssssssssssssssssssssssssss",
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("receiver", &[]), msg).unwrap();
+        assert_eq!(
+            res.messages[1].msg,
+            Asset::Native
+                .send("platform".into(), coin(8, "atom"))
+                .unwrap()
+        );
+
+        let cleanup = ExecuteMsg::Cleanup {
+            ids: Some(vec![confirmed, pending]),
+            limit: None,
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anyone", &[]),
+            cleanup.clone(),
+        )
+        .unwrap();
+        assert!(res.attributes.contains(&attr("removed", "0")));
+        assert!(res.messages.is_empty());
+
+        // a repeated id is removed once and pays its bounty once
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(3600);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("anyone", &[]),
+            ExecuteMsg::Cleanup {
+                ids: Some(vec![confirmed, pending, confirmed, confirmed]),
+                limit: None,
+            },
+        )
+        .unwrap();
+        assert!(res.attributes.contains(&attr("removed", "1")));
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.messages[0].msg,
+            Asset::Native
+                .send("anyone".into(), coin(2, "atom"))
+                .unwrap()
+        );
+        let event = &res.events[0];
+        assert_eq!(event.ty, "transfer_removed");
+        assert!(event
+            .attributes
+            .contains(&attr("transfer_id", hex::encode(confirmed))));
+        assert!(event.attributes.contains(&attr("status", "Confirmed")));
+
+        // a cleanup of an already removed id pays nothing
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("anyone", &[]),
+            cleanup,
+        )
+        .unwrap();
+        assert!(res.attributes.contains(&attr("removed", "0")));
+        assert!(res.messages.is_empty());
+
+        assert!(query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::TransferById { id: confirmed }
+        )
+        .is_err());
+        assert!(query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::TransferById { id: pending }
+        )
+        .is_ok());
+
+        // a cleanup without ids scans the oldest transfers and skips the pending ones
+        let res = execute(
+            deps.as_mut(),
+            env,
+            mock_info("anyone", &[]),
+            ExecuteMsg::Cleanup {
+                ids: None,
+                limit: Some(5),
+            },
+        )
+        .unwrap();
+        assert!(res.attributes.contains(&attr("removed", "0")));
+    }
+
+    #[test]
+    fn cleanup_reaches_the_oldest_finalized_first() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            platform: "platform".into(),
+            cleanup_retention: Some(3600),
+            ..Default::default()
+        };
+        let info = mock_info("owner", &[]);
+        assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        let now = mock_env().block.time.seconds();
+        let mut ids = vec![];
+        for timelock in [now + 3600, now + 3601, now + 3602] {
+            let msg = ExecuteMsg::Fund(TransferMsg {
+                sender: "sender".into(),
+                receiver: "receiver".into(),
+                coin: coin(100, "atom"),
+                hashlock: [
+                    165, 152, 132, 76, 216, 153, 182, 114, 45, 89, 20, 251, 170, 95, 204, 77, 214,
+                    166, 43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
+                ],
+                timelock,
+                allow_hashlock_reuse: true,
+                ..Default::default()
+            });
+            let info = mock_info("sender", &[coin(100, "atom")]);
+            let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+            let id: TransferId = res.data.unwrap().to_vec().try_into().unwrap();
+            ids.push(id);
+        }
+        // the last transfer stays pending, the second is confirmed first
+        let at = |seconds: u64| {
+            let mut env = mock_env();
+            env.block.time = env.block.time.plus_seconds(seconds);
+            env
+        };
+        for (id, seconds) in [(ids[1], 0), (ids[0], 100)] {
+            let msg = ExecuteMsg::ConfirmById {
+                id,
+                secret: *b"ssssssssssssssssssssssssssssssss",
+            };
+            assert!(execute(deps.as_mut(), at(seconds), mock_info("receiver", &[]), msg).is_ok());
+        }
+
+        let cleanup = ExecuteMsg::Cleanup {
+            ids: None,
+            limit: Some(5),
+        };
+        let removed = |res: Response| {
+            res.events
+                .iter()
+                .flat_map(|event| event.attributes.iter())
+                .filter(|attr| attr.key == "transfer_id")
+                .map(|attr| attr.value.clone())
+                .collect::<Vec<_>>()
+        };
+        let res = execute(
+            deps.as_mut(),
+            at(3599),
+            mock_info("anyone", &[]),
+            cleanup.clone(),
+        );
+        assert!(removed(res.unwrap()).is_empty());
+        let res = execute(
+            deps.as_mut(),
+            at(3600),
+            mock_info("anyone", &[]),
+            cleanup.clone(),
+        );
+        assert_eq!(removed(res.unwrap()), vec![hex::encode(ids[1])]);
+        let res = execute(deps.as_mut(), at(7200), mock_info("anyone", &[]), cleanup);
+        assert_eq!(removed(res.unwrap()), vec![hex::encode(ids[0])]);
+
+        assert!(query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::TransferById { id: ids[2] }
+        )
+        .is_ok());
+        assert_eq!(
+            finalized_read(deps.as_ref().storage)
+                .range(None, None, Order::Ascending)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn cross_chain_legs() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            platform: "platform".into(),
+            ..Default::default()
+        };
+        let info = mock_info("owner", &[]);
+        assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        let transfer = TransferMsg {
+            sender: "sender".into(),
+            receiver: "receiver".into(),
+            coin: coin(100, "atom"),
+            hashlock: [
+                165, 152, 132, 76, 216, 153, 182, 114, 45, 89, 20, 251, 170, 95, 204, 77, 214, 166,
+                43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
+            ],
+            timelock: mock_env().block.time.seconds() + 3600,
+            ..Default::default()
+        };
+        let out = TransferOut {
+            dst_chain_id: 1,
+            dst_address: "0x5b38da6a701c568545dcfcb03fcb875f56beddc4".into(),
+            bid_id: 7,
+            dst_token: "0xdac17f958d2ee523a2206206994597c13d831ec7".into(),
+            dst_amount: Uint256::from(99_000_000u128),
+        };
+        let info = mock_info("sender", &[coin(100, "atom")]);
+        let msg = ExecuteMsg::TransferOut {
+            transfer: transfer.clone(),
+            leg: out.clone(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let event = &res.events[0];
+        assert_eq!(event.ty, "new_transfer_out");
+        assert!(event
+            .attributes
+            .contains(&attr("transfer_id", hex::encode(transfer.transfer_id()))));
+        assert!(event.attributes.contains(&attr("bid_id", "7")));
+        assert!(event.attributes.contains(&attr("dst_amount", "99000000")));
+        let record: TransferRecord = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Transfer(transfer.clone()),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(record.leg, Some(CrossChainLeg::Out(out)));
+
+        let transfer = TransferMsg {
+            timelock: transfer.timelock + 1,
+            // the incoming leg of the swap shares its hashlock
+            allow_hashlock_reuse: true,
+            ..transfer
+        };
+        let msg = ExecuteMsg::TransferIn {
+            transfer: transfer.clone(),
+            leg: TransferIn {
+                src_chain_id: 1,
+                src_transfer_id: [1; 32],
+            },
+        };
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let event = &res.events[0];
+        assert_eq!(event.ty, "new_transfer_in");
+        assert!(event
+            .attributes
+            .contains(&attr("src_transfer_id", hex::encode([1; 32]))));
+
+        // a plain fund has no leg
+        let transfer = TransferMsg {
+            timelock: transfer.timelock + 1,
+            ..transfer
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::Fund(transfer.clone()),
+        )
+        .unwrap();
+        assert!(res.events.is_empty());
+        let record: TransferRecord =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::Transfer(transfer)).unwrap())
+                .unwrap();
+        assert_eq!(record.leg, None);
+    }
+
+    fn ibc_chain() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            platform: "platform".into(),
+            ..Default::default()
+        };
+        assert!(instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).is_ok());
+        let connect = mock_ibc_channel_connect_ack("channel-0", IbcOrder::Unordered, IBC_VERSION);
+        assert!(ibc_channel_connect(deps.as_mut(), mock_env(), connect).is_ok());
+        deps
+    }
+
+    /// the running totals must match the ones rebuilt from the records
+    fn assert_totals_rebuild(deps: DepsMut) {
+        let running = asset_totals_read(deps.storage)
+            .range(None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()
+            .unwrap();
+        crate::migrations::rebuild_totals(deps.storage).unwrap();
+        let rebuilt = asset_totals_read(deps.storage)
+            .range(None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()
+            .unwrap();
+        assert_eq!(running, rebuilt);
+    }
+
+    fn sent_packet(res: &Response) -> SwapPacket {
+        res.messages
+            .iter()
+            .find_map(|msg| match &msg.msg {
+                CosmosMsg::Ibc(IbcMsg::SendPacket { data, .. }) => Some(from_json(data).unwrap()),
+                _ => None,
+            })
+            .expect("a packet is sent")
+    }
+
+    fn ibc_transfer_out() -> ExecuteMsg {
+        ExecuteMsg::IbcTransferOut {
+            transfer: TransferMsg {
+                sender: "alice".into(),
+                receiver: "maker-a".into(),
+                coin: coin(100, "atom"),
+                hashlock: [
+                    165, 152, 132, 76, 216, 153, 182, 114, 45, 89, 20, 251, 170, 95, 204, 77, 214,
+                    166, 43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
+                ],
+                timelock: mock_env().block.time.seconds() + 3600,
+                ..Default::default()
+            },
+            leg: TransferOut {
+                dst_address: "alice-b".into(),
+                dst_token: "uosmo".into(),
+                dst_amount: Uint256::from(50u128),
+                ..Default::default()
+            },
+            channel: "channel-0".into(),
+            maker: "maker-b".into(),
+        }
+    }
+
+    #[test]
+    fn ibc_channel_handshake() {
+        let mut deps = mock_dependencies();
+        for (order, version) in [
+            (IbcOrder::Ordered, IBC_VERSION),
+            (IbcOrder::Unordered, "ics20-1"),
+        ] {
+            let open = mock_ibc_channel_open_init("channel-0", order, version);
+            assert_eq!(
+                ibc_channel_open(deps.as_mut(), mock_env(), open),
+                Err(ContractError::InvalidIbcChannel {
+                    version: IBC_VERSION.into()
+                })
+            );
+        }
+        let open = mock_ibc_channel_open_init("channel-0", IbcOrder::Unordered, IBC_VERSION);
+        assert!(ibc_channel_open(deps.as_mut(), mock_env(), open).is_ok());
+
+        // outbound legs need a connected channel
+        let mut deps = ibc_chain();
+        let close = mock_ibc_channel_close_init("channel-0", IbcOrder::Unordered, IBC_VERSION);
+        assert!(ibc_channel_close(deps.as_mut(), mock_env(), close).is_ok());
+        assert_eq!(
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("alice", &[coin(100, "atom")]),
+                ibc_transfer_out()
+            ),
+            Err(ContractError::UnknownChannel {
+                channel: "channel-0".into()
+            })
+        );
+    }
+
+    #[test]
+    fn ibc_swap_relays_the_secret() {
+        let mut chain_a = ibc_chain();
+        let mut chain_b = ibc_chain();
+
+        let res = execute(
+            chain_b.as_mut(),
+            mock_env(),
+            mock_info("maker-b", &[coin(80, "uosmo")]),
+            ExecuteMsg::ProvideLiquidity {
+                channel: "channel-0".into(),
+                remote_receiver: "maker-a".into(),
+            },
+        );
+        assert!(res.is_ok());
+
+        let res = execute(
+            chain_a.as_mut(),
+            mock_env(),
+            mock_info("alice", &[coin(100, "atom")]),
+            ibc_transfer_out(),
+        )
+        .unwrap();
+        let source_id: TransferId = res.data.clone().unwrap().to_vec().try_into().unwrap();
+        let open = sent_packet(&res);
+
+        // the sender cannot send the payout of the maker elsewhere
+        assert_eq!(
+            execute(
+                chain_a.as_mut(),
+                mock_env(),
+                mock_info("alice", &[]),
+                ExecuteMsg::SetForward {
+                    id: source_id,
+                    forward: Some(Forward {
+                        channel: "channel-1".into(),
+                        remote_address: "attacker".into(),
+                        timeout: 600,
+                    }),
+                }
+            ),
+            Err(ContractError::Unauthorized)
+        );
+
+        // the maker pays nothing for a pair without a rate, nor above its rate
+        let recv = mock_ibc_packet_recv("channel-0", &open).unwrap();
+        let res = ibc_packet_receive(chain_b.as_mut(), mock_env(), recv).unwrap();
+        let ack: SwapAck = from_json(&res.acknowledgement).unwrap();
+        assert_eq!(ack, SwapAck::Error(ContractError::NoMakerRate.to_string()));
+        let set_rate = |rate: &str| ExecuteMsg::SetMakerRates {
+            channel: "channel-0".into(),
+            rates: vec![MakerRate {
+                src_denom: "atom".into(),
+                dst_denom: "uosmo".into(),
+                rate: rate.parse().unwrap(),
+                max_amount: Uint128::new(80),
+            }],
+        };
+        assert_eq!(
+            execute(
+                chain_b.as_mut(),
+                mock_env(),
+                mock_info("alice", &[]),
+                set_rate("0.5")
+            ),
+            Err(ContractError::InsufficientLiquidity)
+        );
+        let maker = mock_info("maker-b", &[]);
+        assert!(execute(chain_b.as_mut(), mock_env(), maker.clone(), set_rate("0.4")).is_ok());
+        let recv = mock_ibc_packet_recv("channel-0", &open).unwrap();
+        let res = ibc_packet_receive(chain_b.as_mut(), mock_env(), recv).unwrap();
+        let ack: SwapAck = from_json(&res.acknowledgement).unwrap();
+        assert_eq!(
+            ack,
+            SwapAck::Error(
+                ContractError::QuoteExceeded {
+                    max: Uint128::new(40)
+                }
+                .to_string()
+            )
+        );
+        assert!(execute(chain_b.as_mut(), mock_env(), maker, set_rate("0.5")).is_ok());
+
+        // the counterparty opens the mirrored leg from the liquidity of the maker
+        let recv = mock_ibc_packet_recv("channel-0", &open).unwrap();
+        let res = ibc_packet_receive(chain_b.as_mut(), mock_env(), recv).unwrap();
+        let ack: SwapAck = from_json(&res.acknowledgement).unwrap();
+        let mirror_id: TransferId = match &ack {
+            SwapAck::Result(id) => id.to_vec().try_into().unwrap(),
+            SwapAck::Error(err) => panic!("{}", err),
+        };
+        let liquidity: Liquidity = from_json(
+            query(
+                chain_b.as_ref(),
+                mock_env(),
+                QueryMsg::Liquidity {
+                    channel: "channel-0".into(),
+                    maker: "maker-b".into(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(liquidity.funds, vec![coin(30, "uosmo")]);
+        let totals = asset_totals_read(chain_b.as_ref().storage)
+            .load(b"uosmo")
+            .unwrap();
+        assert_eq!(totals.locked, Uint128::new(50));
+        assert_eq!(totals.reserved, Uint128::new(30));
+        assert_totals_rebuild(chain_b.as_mut());
+
+        let ack = mock_ibc_packet_ack(
+            "channel-0",
+            &open,
+            IbcAcknowledgement::encode_json(&ack).unwrap(),
+        )
+        .unwrap();
+        assert!(ibc_packet_ack(chain_a.as_mut(), mock_env(), ack).is_ok());
+        let record: TransferRecord = from_json(
+            query(
+                chain_a.as_ref(),
+                mock_env(),
+                QueryMsg::TransferById { id: source_id },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            record.ibc,
+            Some(IbcLeg::Source {
+                channel: "channel-0".into(),
+                remote_timelock: mock_env().block.time.seconds() + 1800,
+                remote_id: Some(mirror_id),
+            })
+        );
+
+        // revealing the secret on the mirrored leg confirms the source leg
+        let res = execute(
+            chain_b.as_mut(),
+            mock_env(),
+            mock_info("alice-b", &[]),
+            ExecuteMsg::ConfirmById {
+                id: mirror_id,
+                secret: *b"ssssssssssssssssssssssssssssssss",
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            Asset::Native
+                .send("alice-b".into(), coin(50, "uosmo"))
+                .unwrap()
+        );
+        let reveal = sent_packet(&res);
+        let recv = mock_ibc_packet_recv("channel-0", &reveal).unwrap();
+        let res = ibc_packet_receive(chain_a.as_mut(), mock_env(), recv).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            Asset::Native
+                .send("maker-a".into(), coin(100, "atom"))
+                .unwrap()
+        );
+        let record: TransferRecord = from_json(
+            query(
+                chain_a.as_ref(),
+                mock_env(),
+                QueryMsg::TransferById { id: source_id },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(record.status, TransferStatus::Confirmed);
+        assert_totals_rebuild(chain_a.as_mut());
+        assert_totals_rebuild(chain_b.as_mut());
+    }
+
+    #[test]
+    fn ibc_source_leg_pays_the_maker() {
+        let mut deps = ibc_chain();
+
+        // the maker never checks the id of the source leg, and the relayed reveal confirming it
+        // cannot be held off
+        let forward = Forward {
+            channel: "channel-1".into(),
+            remote_address: "attacker".into(),
+            timeout: 600,
+        };
+        let terms: [fn(&mut TransferMsg, Forward); 3] = [
+            |transfer, forward| transfer.forward = Some(forward),
+            |transfer, _| transfer.exclusive_window = Some(600),
+            |transfer, _| transfer.delegate = Some("delegate".into()),
+        ];
+        for set in terms {
+            let mut msg = ibc_transfer_out();
+            if let ExecuteMsg::IbcTransferOut { transfer, .. } = &mut msg {
+                set(transfer, forward.clone());
+            }
+            assert_eq!(
+                execute(
+                    deps.as_mut(),
+                    mock_env(),
+                    mock_info("alice", &[coin(100, "atom")]),
+                    msg
+                ),
+                Err(ContractError::InvalidIbcTerms)
+            );
+        }
+    }
+
+    #[test]
+    fn ibc_failed_open_refunds_the_source() {
+        let mut chain_a = ibc_chain();
+        let mut chain_b = ibc_chain();
+
+        let res = execute(
+            chain_a.as_mut(),
+            mock_env(),
+            mock_info("alice", &[coin(100, "atom")]),
+            ibc_transfer_out(),
+        )
+        .unwrap();
+        let open = sent_packet(&res);
+
+        // without liquidity of the maker the open is acknowledged as an error
+        let recv = mock_ibc_packet_recv("channel-0", &open).unwrap();
+        let res = ibc_packet_receive(chain_b.as_mut(), mock_env(), recv).unwrap();
+        let ack: SwapAck = from_json(&res.acknowledgement).unwrap();
+        assert_eq!(
+            ack,
+            SwapAck::Error(ContractError::InsufficientLiquidity.to_string())
+        );
+
+        let ack = mock_ibc_packet_ack(
+            "channel-0",
+            &open,
+            IbcAcknowledgement::encode_json(&ack).unwrap(),
+        )
+        .unwrap();
+        let res = ibc_packet_ack(chain_a.as_mut(), mock_env(), ack).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            Asset::Native
+                .send("alice".into(), coin(100, "atom"))
+                .unwrap()
+        );
+
+        // a timeout after the refund pays nothing twice
+        let timeout = mock_ibc_packet_timeout("channel-0", &open).unwrap();
+        assert_eq!(
+            ibc_packet_timeout(chain_a.as_mut(), mock_env(), timeout),
+            Err(ContractError::TransferNotPending)
+        );
+    }
+
+    #[test]
+    fn pause_keeps_refunds_for_sender() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            platform: "platform".into(),
+            fees: vec![],
+            refund_fee: false,
+            ..Default::default()
+        };
+        let info = mock_info("owner", &[]);
+        assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        let transfer_msg = TransferMsg {
+            sender: "sender".into(),
+            receiver: "receiver".into(),
+            coin: coin(100, "atom"),
+            hashlock: [
+                165, 152, 132, 76, 216, 153, 182, 114, 45, 89, 20, 251, 170, 95, 204, 77, 214, 166,
+                43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
+            ],
+            timelock: mock_env().block.time.seconds() + DEFAULT_MIN_TIMELOCK,
+            ..Default::default()
+        };
+        let info = mock_info("sender", &[coin(100, "atom")]);
+        let msg = ExecuteMsg::Fund(transfer_msg.clone());
+        assert!(execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).is_ok());
+
+        let pause = ExecuteMsg::SetPause {
+            fund: Some(true),
+            confirm: None,
+            refund: Some(true),
+        };
+        assert_eq!(
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("sender", &[]),
+                pause.clone()
+            ),
+            Err(ContractError::Unauthorized)
+        );
+        let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), pause).unwrap();
+        assert!(res.attributes.contains(&attr("fund", "true")));
+        let state: PauseState =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::PauseState {}).unwrap()).unwrap();
+        assert_eq!(
+            state,
+            PauseState {
+                fund: true,
+                confirm: false,
+                refund: true,
+            }
+        );
+
+        assert_eq!(
+            execute(deps.as_mut(), mock_env(), info, msg),
+            Err(ContractError::Paused {
+                operation: "fund".into()
+            })
+        );
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(DEFAULT_MIN_TIMELOCK + 1);
+        let msg = ExecuteMsg::Refund(transfer_msg);
+        assert_eq!(
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info("relayer", &[]),
+                msg.clone()
+            ),
+            Err(ContractError::Paused {
+                operation: "refund".into()
+            })
+        );
+        assert!(execute(deps.as_mut(), env, mock_info("sender", &[]), msg).is_ok());
+    }
+
+    #[test]
+    fn sudo_operations() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            platform: "platform".into(),
+            fees: vec![FeeRule {
+                denom: "atom".into(),
+                rate: FeeRate::Flat(coin(1, "atom")),
+            }],
+            refund_fee: false,
+            ..Default::default()
+        };
+        let info = mock_info("owner", &[]);
+        assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        let transfer_msg = TransferMsg {
+            sender: "sender".into(),
+            receiver: "receiver".into(),
+            coin: coin(100, "atom"),
+            hashlock: [
+                165, 152, 132, 76, 216, 153, 182, 114, 45, 89, 20, 251, 170, 95, 204, 77, 214, 166,
+                43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
+            ],
+            timelock: mock_env().block.time.seconds() + DEFAULT_MIN_TIMELOCK,
+            tip: Some(coin(2, "atom")),
+            ..Default::default()
+        };
+        let id = transfer_msg.transfer_id();
+        let info = mock_info("sender", &[coin(103, "atom")]);
+        let msg = ExecuteMsg::Fund(transfer_msg);
+        assert!(execute(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        // the refund happens before expiry, pause included, and pays everything back to the sender
+        let pause = SudoMsg::Pause {
+            fund: None,
+            confirm: None,
+            refund: Some(true),
+        };
+        let res = sudo(deps.as_mut(), mock_env(), pause).unwrap();
+        assert_eq!(res.events[0].ty, "sudo");
+        assert!(res.events[0].attributes.contains(&attr("refund", "true")));
+
+        let res = sudo(deps.as_mut(), mock_env(), SudoMsg::ForceRefund { id }).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.messages[0].msg,
+            Asset::Native
+                .send("sender".into(), coin(103, "atom"))
+                .unwrap()
+        );
+        let event = &res.events[0];
+        assert!(event
+            .attributes
+            .contains(&attr("operation", "force_refund")));
+        assert!(event
+            .attributes
+            .contains(&attr("transfer_id", hex::encode(id))));
+        let record: TransferRecord =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::TransferById { id }).unwrap())
+                .unwrap();
+        assert_eq!(record.status, TransferStatus::Refunded);
+        assert_eq!(
+            sudo(deps.as_mut(), mock_env(), SudoMsg::ForceRefund { id }),
+            Err(ContractError::TransferNotPending)
+        );
+
+        let set_platform = SudoMsg::SetPlatform {
+            platform: "treasury".into(),
+        };
+        let res = sudo(deps.as_mut(), mock_env(), set_platform).unwrap();
+        assert!(res.events[0]
+            .attributes
+            .contains(&attr("previous", "platform")));
+        let config: Config =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+        assert_eq!(config.platform, "treasury");
+    }
+
+    #[test]
+    fn asset_limits() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            platform: "platform".into(),
+            fees: vec![],
+            refund_fee: false,
+            restrict_assets: true,
+            ..Default::default()
+        };
+        let info = mock_info("owner", &[]);
+        assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        let fund = |deps: DepsMut, hashlock: HashLock, amount: u128| {
+            let msg = ExecuteMsg::Fund(TransferMsg {
+                sender: "sender".into(),
+                receiver: "receiver".into(),
+                coin: coin(amount, "atom"),
+                hashlock,
+                timelock: mock_env().block.time.seconds() + DEFAULT_MIN_TIMELOCK,
+                ..Default::default()
+            });
+            let info = mock_info("sender", &[coin(amount, "atom")]);
+            execute(deps, mock_env(), info, msg)
+        };
+        assert_eq!(
+            fund(deps.as_mut(), [1; 32], 50),
+            Err(ContractError::AssetNotAllowed {
+                denom: "atom".into()
+            })
+        );
+
+        let set_limits = |limits: AssetLimits| ExecuteMsg::SetAssetLimits {
+            denom: "atom".into(),
+            limits: Some(limits),
+        };
+        let limits = AssetLimits {
+            min: Uint128::new(10),
+            max: Some(Uint128::new(100)),
+            tvl_cap: Some(Uint128::new(150)),
+        };
+        assert_eq!(
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("sender", &[]),
+                set_limits(limits.clone())
+            ),
+            Err(ContractError::Unauthorized)
+        );
+        let inverted = AssetLimits {
+            min: Uint128::new(101),
+            ..limits.clone()
+        };
+        assert_eq!(
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("owner", &[]),
+                set_limits(inverted)
+            ),
+            Err(ContractError::InvalidAssetLimits)
+        );
+        let info = mock_info("owner", &[]);
+        assert!(execute(deps.as_mut(), mock_env(), info, set_limits(limits.clone())).is_ok());
+        let listed: Option<AssetLimits> = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::AssetLimits {
+                    denom: "atom".into(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(listed, Some(limits.clone()));
+        let list: Vec<(String, AssetLimits)> =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::AssetList {}).unwrap()).unwrap();
+        assert_eq!(list, vec![("atom".to_string(), limits)]);
+
+        assert_eq!(
+            fund(deps.as_mut(), [1; 32], 9),
+            Err(ContractError::AmountBelowMinimum {
+                min: Uint128::new(10)
+            })
+        );
+        assert_eq!(
+            fund(deps.as_mut(), [1; 32], 101),
+            Err(ContractError::AmountAboveMaximum {
+                max: Uint128::new(100)
+            })
+        );
+        let hashlock = [
+            165, 152, 132, 76, 216, 153, 182, 114, 45, 89, 20, 251, 170, 95, 204, 77, 214, 166, 43,
+            58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
+        ];
+        let res = fund(deps.as_mut(), hashlock, 100).unwrap();
+        let id: TransferId = res.data.unwrap().to_vec().try_into().unwrap();
+        assert_eq!(
+            fund(deps.as_mut(), [1; 32], 60),
+            Err(ContractError::TvlCapExceeded {
+                cap: Uint128::new(150)
+            })
+        );
+
+        // a finalized transfer no longer counts towards the cap
+        let msg = ExecuteMsg::ConfirmById {
+            id,
+            secret: *b"ssssssssssssssssssssssssssssssss",
+        };
+        assert!(execute(deps.as_mut(), mock_env(), mock_info("receiver", &[]), msg).is_ok());
+        assert!(fund(deps.as_mut(), [1; 32], 60).is_ok());
+    }
+
+    #[test]
+    fn forwarded_payout_timeout() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            platform: "platform".into(),
+            fees: vec![],
+            refund_fee: false,
+            ..Default::default()
+        };
+        let info = mock_info("owner", &[]);
+        assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        let forward = Forward {
+            channel: "channel-0".into(),
+            remote_address: "osmo1receiver".into(),
+            timeout: 0,
+        };
+        let transfer_msg = TransferMsg {
+            sender: "sender".into(),
+            receiver: "receiver".into(),
+            coin: coin(100, "atom"),
+            hashlock: [
+                165, 152, 132, 76, 216, 153, 182, 114, 45, 89, 20, 251, 170, 95, 204, 77, 214, 166,
+                43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
+            ],
+            timelock: mock_env().block.time.seconds() + DEFAULT_MIN_TIMELOCK,
+            ..Default::default()
+        };
+        let info = mock_info("sender", &[coin(100, "atom")]);
+        let msg = ExecuteMsg::Fund(TransferMsg {
+            forward: Some(forward.clone()),
+            ..transfer_msg.clone()
+        });
+        assert_eq!(
+            execute(deps.as_mut(), mock_env(), info.clone(), msg),
+            Err(ContractError::InvalidForward)
+        );
+
+        // forward instructions set by the sender are part of the id the receiver expects
+        let funded_forward = TransferMsg {
+            forward: Some(Forward {
+                remote_address: "osmo1other".into(),
+                timeout: 600,
+                ..forward.clone()
+            }),
+            ..transfer_msg.clone()
+        };
+        assert_ne!(funded_forward.transfer_id(), transfer_msg.transfer_id());
+        let msg = ExecuteMsg::Fund(funded_forward.clone());
+        assert!(execute(deps.as_mut(), mock_env(), info.clone(), msg).is_ok());
+        let record: TransferRecord = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::TransferById {
+                    id: funded_forward.transfer_id(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(record.forward, funded_forward.forward);
+
+        let msg = ExecuteMsg::Fund(TransferMsg {
+            allow_hashlock_reuse: true,
+            ..transfer_msg.clone()
+        });
+        assert!(execute(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        // only the receiver changes where its payout goes
+        let set_forward = |forward: &Forward| ExecuteMsg::SetForward {
+            id: transfer_msg.transfer_id(),
+            forward: Some(forward.clone()),
+        };
+        let forward = Forward {
+            timeout: 600,
+            ..forward
+        };
+        assert_eq!(
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("sender", &[]),
+                set_forward(&Forward {
+                    remote_address: "osmo1attacker".into(),
+                    ..forward.clone()
+                })
+            ),
+            Err(ContractError::Unauthorized)
+        );
+        for timeout in [0, DEFAULT_MAX_TIMELOCK + 1, u64::MAX] {
+            assert_eq!(
+                execute(
+                    deps.as_mut(),
+                    mock_env(),
+                    mock_info("receiver", &[]),
+                    set_forward(&Forward {
+                        timeout,
+                        ..forward.clone()
+                    })
+                ),
+                Err(ContractError::InvalidForward)
+            );
+        }
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("receiver", &[]),
+            set_forward(&forward),
+        )
+        .unwrap();
+        assert!(res
+            .attributes
+            .contains(&attr("remote_address", "osmo1receiver")));
+
+        let msg = ExecuteMsg::Confirm((transfer_msg, *b"ssssssssssssssssssssssssssssssss"));
+        let res = execute(deps.as_mut(), mock_env(), mock_info("receiver", &[]), msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        let sub_msg = &res.messages[0];
+        assert_eq!(sub_msg.reply_on, ReplyOn::Always);
+        match &sub_msg.msg {
+            CosmosMsg::Stargate { type_url, value } => {
+                assert_eq!(type_url, "/ibc.applications.transfer.v1.MsgTransfer");
+                assert!(value.starts_with(b"\n\x08transfer\x12\x09channel-0"));
+            }
+            msg => panic!("unexpected message {msg:?}"),
+        }
+
+        let packet = Event::new("send_packet")
+            .add_attribute("packet_src_channel", "channel-0")
+            .add_attribute("packet_sequence", "7");
+        let reply_msg = Reply {
+            id: sub_msg.id,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![packet],
+                data: None,
+            }),
+        };
+        assert!(forward::reply(deps.as_mut(), mock_env(), reply_msg).is_ok());
+
+        // the timed out transfer is returned to the contract and credited to the receiver
+        let timeout = SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcTimeout {
+            channel: "channel-0".into(),
+            sequence: 7,
+        });
+        let res = sudo(deps.as_mut(), mock_env(), timeout.clone()).unwrap();
+        assert!(res.attributes.contains(&attr("delivered", "false")));
+        let claimable: Vec<Coin> = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Claimable {
+                    address: "receiver".into(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(claimable, vec![coin(100, "atom")]);
+
+        // a repeated callback credits nothing
+        assert!(sudo(deps.as_mut(), mock_env(), timeout).is_ok());
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("receiver", &[]),
+            ExecuteMsg::Claim {},
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            Asset::Native
+                .send("receiver".into(), coin(100, "atom"))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn timelock_bounds() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            platform: "platform".into(),
+            min_timelock: Some(60),
+            max_timelock: Some(3600),
+            ..Default::default()
+        };
+        let info = mock_info("owner", &[]);
+        assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        let now = mock_env().block.time.seconds();
+        let fund = |timelock: u64| {
+            ExecuteMsg::Fund(TransferMsg {
+                sender: "sender".into(),
+                receiver: "receiver".into(),
+                coin: coin(100, "atom"),
+                hashlock: [
+                    165, 152, 132, 76, 216, 153, 182, 114, 45, 89, 20, 251, 170, 95, 204, 77, 214,
+                    166, 43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
+                ],
+                timelock,
+                allow_hashlock_reuse: true,
+                ..Default::default()
+            })
+        };
+        let info = mock_info("sender", &[coin(100, "atom")]);
+        for timelock in [1, now + 59, now + 3601] {
+            assert_eq!(
+                execute(deps.as_mut(), mock_env(), info.clone(), fund(timelock)),
+                Err(ContractError::InvalidTimelock {
+                    min: now + 60,
+                    max: now + 3600
+                })
+            );
+        }
+        assert!(execute(deps.as_mut(), mock_env(), info.clone(), fund(now + 60)).is_ok());
+        assert!(execute(deps.as_mut(), mock_env(), info, fund(now + 3600)).is_ok());
+
+        let update = ExecuteMsg::UpdateConfig {
+            platform: None,
+            fees: None,
+            refund_fee: None,
+            min_timelock: Some(7200),
+            max_timelock: None,
+            min_height_lock: None,
+            max_height_lock: None,
+            cleanup_retention: None,
+            cleanup_bounty_bps: None,
+            strict_payment: None,
+            restrict_assets: None,
+            fee_recipients: None,
+            referrer_share_bps: None,
+        };
+        assert_eq!(
+            execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), update),
+            Err(ContractError::InvalidTimelockBounds)
+        );
+    }
+
+    #[test]
+    fn height_timelock() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            platform: "platform".into(),
+            min_height_lock: Some(10),
+            max_height_lock: Some(1000),
+            ..Default::default()
+        };
+        let info = mock_info("owner", &[]);
+        assert!(instantiate(deps.as_mut(), mock_env(), info, msg).is_ok());
+
+        let height = mock_env().block.height;
+        let transfer_msg = TransferMsg {
+            sender: "sender".into(),
+            receiver: "receiver".into(),
+            coin: coin(100, "atom"),
+            hashlock: [
+                165, 152, 132, 76, 216, 153, 182, 114, 45, 89, 20, 251, 170, 95, 204, 77, 214, 166,
+                43, 58, 171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
+            ],
+            timelock: height + 1001,
+            timelock_kind: TimelockKind::Height,
+            ..Default::default()
+        };
+        let info = mock_info("sender", &[coin(100, "atom")]);
+        assert_eq!(
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                info.clone(),
+                ExecuteMsg::Fund(transfer_msg.clone())
+            ),
+            Err(ContractError::InvalidTimelock {
+                min: height + 10,
+                max: height + 1000
+            })
+        );
+
+        let transfer_msg = TransferMsg {
+            timelock: height + 10,
+            ..transfer_msg
+        };
+        assert_ne!(
+            transfer_msg.transfer_id(),
+            TransferMsg {
+                timelock_kind: TimelockKind::Timestamp,
+                ..transfer_msg.clone()
+            }
+            .transfer_id()
+        );
+        let msg = ExecuteMsg::Fund(transfer_msg.clone());
+        assert!(execute(deps.as_mut(), mock_env(), info, msg).is_ok());
+        let record: TransferRecord = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Transfer(transfer_msg.clone()),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(record.timelock_kind, TimelockKind::Height);
+
+        // the refund follows the block height, not the block time
+        let mut env = mock_env();
+        env.block.height += 10;
+        env.block.time = env.block.time.plus_seconds(DEFAULT_MAX_TIMELOCK);
+        let msg = ExecuteMsg::Refund(transfer_msg);
+        assert_eq!(
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info("sender", &[]),
+                msg.clone()
+            ),
+            Err(ContractError::TransferLocked)
+        );
+        env.block.height += 1;
+        assert!(execute(deps.as_mut(), env, mock_info("sender", &[]), msg).is_ok());
+    }
+
+    #[test]
+    fn json_hashes() {
+        let msg = ExecuteMsg::ConfirmById {
+            id: [1; 32],
+            secret: [0xab; 32],
+        };
+        let json = to_json_string(&msg).unwrap();
+        assert_eq!(
+            json,
+            format!(
+                r#"{{"confirm_by_id":{{"id":"{}","secret":"{}"}}}}"#,
+                "01".repeat(32),
+                "ab".repeat(32)
+            )
+        );
+        assert_eq!(from_json::<ExecuteMsg>(&json).unwrap(), msg);
+
+        // the array form of the previous format, base64 and 0x-prefixed hex still parse
+        let legacy = format!(
+            r#"{{"confirm_by_id":{{"id":{:?},"secret":"0x{}"}}}}"#,
+            [1u8; 32],
+            "ab".repeat(32)
+        );
+        assert_eq!(from_json::<ExecuteMsg>(legacy).unwrap(), msg);
+        let base64 = format!(
+            r#"{{"confirm_by_id":{{"id":"{}","secret":{:?}}}}}"#,
+            Binary::from([1u8; 32]).to_base64(),
+            [0xabu8; 32]
+        );
+        assert_eq!(from_json::<ExecuteMsg>(base64).unwrap(), msg);
+        let short = format!(r#"{{"refund_by_id":{{"id":{:?}}}}}"#, [1u8; 31]);
+        assert!(from_json::<ExecuteMsg>(short).is_err());
+        assert!(from_json::<ExecuteMsg>(r#"{"refund_by_id":{"id":"0101"}}"#).is_err());
+
+        let msgs = [
+            (
+                ExecuteMsg::Confirm((
+                    TransferMsg {
+                        hashlock: [2; 32],
+                        ..Default::default()
+                    },
+                    [3; 32],
+                )),
+                ["02", "03"],
+            ),
+            (
+                ExecuteMsg::BatchConfirm {
+                    items: vec![([4; 32], [5; 32])],
+                    best_effort: false,
+                },
+                ["04", "05"],
+            ),
+        ];
+        for (msg, bytes) in msgs {
+            let json = to_json_string(&msg).unwrap();
+            for byte in bytes {
+                assert!(json.contains(&format!(r#""{}""#, byte.repeat(32))));
+            }
+            assert_eq!(from_json::<ExecuteMsg>(&json).unwrap(), msg);
+        }
+        assert_eq!(
+            from_json::<ExecuteMsg>(r#"{"cleanup":{}}"#).unwrap(),
+            ExecuteMsg::Cleanup {
+                ids: None,
+                limit: None
+            }
+        );
+    }
+
+    #[test]
+    fn checked_in_schemas_are_current() {
+        use cosmwasm_schema::schema_for;
+
+        let generated = |schema| serde_json::to_string_pretty(&schema).unwrap() + "\n";
+        assert_eq!(
+            include_str!("../schema/execute_msg.json"),
+            generated(schema_for!(ExecuteMsg))
+        );
+        assert_eq!(
+            include_str!("../schema/query_msg.json"),
+            generated(schema_for!(QueryMsg))
+        );
+        assert_eq!(
+            include_str!("../schema/receive_msg.json"),
+            generated(schema_for!(ReceiveMsg))
+        );
+        assert_eq!(
+            include_str!("../schema/transfer_msg.json"),
+            generated(schema_for!(TransferMsg))
+        );
+        assert_eq!(
+            include_str!("../schema/sudo_msg.json"),
+            generated(schema_for!(SudoMsg))
+        );
+        assert_eq!(
+            include_str!("../schema/batch_item_result.json"),
+            generated(schema_for!(BatchItemResult))
+        );
+    }
 }
@@ -0,0 +1,162 @@
+//! The typed client against a multi-test chain standing in for the RPC transport.
+use std::cell::{Cell, RefCell};
+
+use cosmwasm_std::{
+    coin, coins, from_json, to_json_vec, Addr, ContractResult, QueryRequest, SystemResult,
+    WasmQuery,
+};
+use cw_multi_test::Executor;
+use prost::Message;
+use sha3::{Digest, Sha3_256};
+
+use cosmos_atomic_swap::client::{
+    proto, Account, Client, ClientError, InstantiateBuilder, Signer, TransferBuilder, Transport,
+    TxResponse, MSG_EXECUTE_CONTRACT,
+};
+use cosmos_atomic_swap::{ExecuteMsg, TransferStatus};
+
+use crate::suite::*;
+
+const CHAIN_ID: &str = "testing";
+
+struct MockSigner;
+
+impl Signer for MockSigner {
+    fn address(&self) -> String {
+        SENDER.into()
+    }
+
+    fn public_key(&self) -> Vec<u8> {
+        vec![2; 33]
+    }
+
+    fn sign(&self, sign_doc: &[u8]) -> Result<Vec<u8>, ClientError> {
+        Ok(Sha3_256::digest(sign_doc).to_vec())
+    }
+}
+
+/// decodes the signed transactions and runs them on the suite
+struct MockTransport {
+    suite: RefCell<Suite>,
+    sequence: Cell<u64>,
+}
+
+impl Transport for MockTransport {
+    fn account(&self, _address: &str) -> Result<Account, ClientError> {
+        Ok(Account {
+            account_number: 7,
+            sequence: self.sequence.get(),
+        })
+    }
+
+    fn query_smart(&self, contract: &str, query: &[u8]) -> Result<Vec<u8>, ClientError> {
+        let request = QueryRequest::<cosmwasm_std::Empty>::Wasm(WasmQuery::Smart {
+            contract_addr: contract.into(),
+            msg: query.to_vec().into(),
+        });
+        let suite = self.suite.borrow();
+        match suite.app.wrap().raw_query(&to_json_vec(&request)?) {
+            SystemResult::Ok(ContractResult::Ok(res)) => Ok(res.to_vec()),
+            err => Err(ClientError::Transport(format!("{:?}", err))),
+        }
+    }
+
+    fn broadcast(&self, tx: &[u8]) -> Result<TxResponse, ClientError> {
+        let decode = |err: prost::DecodeError| ClientError::Transport(err.to_string());
+        let tx = proto::TxRaw::decode(tx).map_err(decode)?;
+        let sign_doc = proto::SignDoc {
+            body_bytes: tx.body_bytes.clone(),
+            auth_info_bytes: tx.auth_info_bytes.clone(),
+            chain_id: CHAIN_ID.into(),
+            account_number: 7,
+        };
+        assert_eq!(
+            tx.signatures,
+            vec![MockSigner.sign(&sign_doc.encode_to_vec())?]
+        );
+        let auth_info = proto::AuthInfo::decode(tx.auth_info_bytes.as_slice()).map_err(decode)?;
+        assert_eq!(auth_info.signer_infos[0].sequence, self.sequence.get());
+        self.sequence.set(self.sequence.get() + 1);
+
+        let body = proto::TxBody::decode(tx.body_bytes.as_slice()).map_err(decode)?;
+        assert_eq!(body.messages[0].type_url, MSG_EXECUTE_CONTRACT);
+        let msg =
+            proto::MsgExecuteContract::decode(body.messages[0].value.as_slice()).map_err(decode)?;
+        let funds: Vec<_> = msg
+            .funds
+            .iter()
+            .map(|fund| coin(fund.amount.parse().unwrap(), &fund.denom))
+            .collect();
+        let execute: ExecuteMsg = from_json(&msg.msg)?;
+        let res = self.suite.borrow_mut().app.execute_contract(
+            Addr::unchecked(msg.sender),
+            Addr::unchecked(msg.contract),
+            &execute,
+            &funds,
+        );
+        Ok(match res {
+            Ok(res) => TxResponse {
+                data: res.data.map(|data| data.to_vec()).unwrap_or_default(),
+                ..Default::default()
+            },
+            Err(err) => TxResponse {
+                code: 5,
+                raw_log: err.root_cause().to_string(),
+                ..Default::default()
+            },
+        })
+    }
+}
+
+fn client() -> Client<MockTransport, MockSigner> {
+    let msg = InstantiateBuilder::new(PLATFORM)
+        .fee(cosmos_atomic_swap::FeeRule {
+            denom: "atom".into(),
+            rate: cosmos_atomic_swap::FeeRate::Flat(coin(1, "atom")),
+        })
+        .build();
+    let suite = Suite::new(msg, &[(SENDER, coins(1000, "atom"))]);
+    let contract = suite.contract.to_string();
+    let transport = MockTransport {
+        suite: RefCell::new(suite),
+        sequence: Cell::new(0),
+    };
+    Client::new(transport, MockSigner, CHAIN_ID, contract)
+}
+
+#[test]
+fn client_funds_and_confirms() {
+    let client = client();
+    let timelock = client
+        .transport
+        .suite
+        .borrow()
+        .app
+        .block_info()
+        .time
+        .seconds()
+        + 3600;
+
+    let msg = TransferBuilder::new(SENDER, RECEIVER, coin(100, "atom"))
+        .secret(SECRET)
+        .timelock(timelock)
+        .tip(coin(2, "atom"))
+        .build();
+    assert_eq!(msg.hashlock, HASHLOCK);
+    let id = client.fund(msg).unwrap();
+    let record = client.transfer(id).unwrap();
+    assert_eq!(record.status, TransferStatus::Pending);
+    assert_eq!(client.transport.suite.borrow().balance(SENDER, "atom"), 897);
+
+    let err = client.confirm(id, [0; 32]).unwrap_err();
+    assert!(matches!(err, ClientError::TxFailed { code: 5, .. }));
+    client.confirm(id, SECRET).unwrap();
+    assert_eq!(
+        client.transfer(id).unwrap().status,
+        TransferStatus::Confirmed
+    );
+    let suite = client.transport.suite.borrow();
+    assert_eq!(suite.balance(RECEIVER, "atom"), 100);
+    // the signer submitted the confirm and collects its own tip
+    assert_eq!(suite.balance(SENDER, "atom"), 899);
+}
@@ -0,0 +1,753 @@
+#[cfg(feature = "client")]
+mod client;
+mod suite;
+
+use cosmwasm_std::{coin, coins, from_json, Addr, Coin, Uint128};
+use cw_multi_test::Executor;
+
+use cosmos_atomic_swap::premium::PremiumStatus;
+use cosmos_atomic_swap::{
+    BatchItemResult, ContractError, ExecuteMsg, FeeRate, FeeRecipient, FeeRule, Forward,
+    HashlockState, InstantiateMsg, PremiumTerms, QueryMsg, Solvency, TransferMsg, TransferStatus,
+    DEFAULT_MIN_TIMELOCK,
+};
+use suite::*;
+
+fn flat_fee(denom: &str, amount: u128) -> FeeRule {
+    FeeRule {
+        denom: denom.into(),
+        rate: FeeRate::Flat(coin(amount, denom)),
+    }
+}
+
+fn native_suite(refund_fee: bool) -> Suite {
+    Suite::new(
+        InstantiateMsg {
+            platform: PLATFORM.into(),
+            fees: vec![flat_fee("atom", 1)],
+            refund_fee,
+            ..Default::default()
+        },
+        &[(SENDER, coins(1000, "atom"))],
+    )
+}
+
+#[test]
+fn fund_and_confirm_move_funds() {
+    let mut suite = native_suite(false);
+    let contract = suite.contract.clone();
+
+    let msg = suite.transfer_msg(coin(100, "atom"), 3600);
+    let id = suite.fund(SENDER, msg, &coins(101, "atom")).unwrap();
+    assert_eq!(suite.balance(SENDER, "atom"), 899);
+    assert_eq!(suite.balance(&contract, "atom"), 101);
+
+    suite.confirm(RECEIVER, id, SECRET).unwrap();
+    assert_eq!(suite.balance(RECEIVER, "atom"), 100);
+    assert_eq!(suite.balance(PLATFORM, "atom"), 1);
+    assert_eq!(suite.balance(&contract, "atom"), 0);
+    assert_eq!(suite.transfer(id).status, TransferStatus::Confirmed);
+    assert_eq!(suite.transfer(id).secret_key, SECRET);
+}
+
+#[test]
+fn refund_after_expiry_moves_funds() {
+    for (refund_fee, sender, platform) in [(false, 999, 1), (true, 1000, 0)] {
+        let mut suite = native_suite(refund_fee);
+        let contract = suite.contract.clone();
+
+        let msg = suite.transfer_msg(coin(100, "atom"), DEFAULT_MIN_TIMELOCK);
+        let id = suite.fund(SENDER, msg, &coins(101, "atom")).unwrap();
+
+        suite.advance_seconds(DEFAULT_MIN_TIMELOCK + 1);
+        suite.refund(SENDER, id).unwrap();
+        assert_eq!(suite.balance(SENDER, "atom"), sender);
+        assert_eq!(suite.balance(PLATFORM, "atom"), platform);
+        assert_eq!(suite.balance(RECEIVER, "atom"), 0);
+        assert_eq!(suite.balance(&contract, "atom"), 0);
+        assert_eq!(suite.transfer(id).status, TransferStatus::Refunded);
+    }
+}
+
+#[test]
+fn failures_keep_balances() {
+    let mut suite = native_suite(false);
+    let contract = suite.contract.clone();
+
+    let msg = suite.transfer_msg(coin(100, "atom"), 3600);
+    let err = suite
+        .fund(SENDER, msg.clone(), &coins(100, "atom"))
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::InsufficientFundsSend
+    );
+    assert_eq!(suite.balance(SENDER, "atom"), 1000);
+
+    let id = suite.fund(SENDER, msg, &coins(101, "atom")).unwrap();
+    let err = suite.confirm(RECEIVER, id, [0; 32]).unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::IncorrectSecret
+    );
+    let err = suite.refund(SENDER, id).unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::TransferLocked
+    );
+    assert_eq!(suite.balance(&contract, "atom"), 101);
+    assert_eq!(suite.balance(SENDER, "atom"), 899);
+    assert_eq!(suite.balance(RECEIVER, "atom"), 0);
+    assert_eq!(suite.balance(PLATFORM, "atom"), 0);
+
+    // the receiver cannot be paid twice, nor the sender refunded after confirm
+    suite.confirm(RECEIVER, id, SECRET).unwrap();
+    assert!(suite.confirm(RECEIVER, id, SECRET).is_err());
+    suite.advance_seconds(3601);
+    assert!(suite.refund(SENDER, id).is_err());
+    assert_eq!(suite.balance(RECEIVER, "atom"), 100);
+    assert_eq!(suite.balance(SENDER, "atom"), 899);
+}
+
+#[test]
+fn cw20_fund_and_confirm_move_tokens() {
+    let mut suite = Suite::new(
+        InstantiateMsg {
+            platform: PLATFORM.into(),
+            ..Default::default()
+        },
+        &[],
+    );
+    let token = suite.create_cw20("TOKEN", &[(SENDER, 1000)]);
+    let contract = suite.contract.clone();
+    let set_fee = |fee: Coin| ExecuteMsg::UpdateConfig {
+        platform: None,
+        fees: Some(vec![FeeRule {
+            denom: token.to_string(),
+            rate: FeeRate::Flat(fee),
+        }]),
+        refund_fee: None,
+        min_timelock: None,
+        max_timelock: None,
+        min_height_lock: None,
+        max_height_lock: None,
+        cleanup_retention: None,
+        cleanup_bounty_bps: None,
+        strict_payment: None,
+        restrict_assets: None,
+        fee_recipients: None,
+        referrer_share_bps: None,
+    };
+
+    // a token transfer carries no native coins to pay a flat fee with
+    let err = suite
+        .execute(OWNER, &set_fee(coin(2, "atom")), &[])
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::InvalidFeeRule {
+            denom: token.to_string()
+        }
+    );
+    suite
+        .execute(OWNER, &set_fee(coin(2, token.as_str())), &[])
+        .unwrap();
+
+    let msg = suite.transfer_msg(coin(100, token.as_str()), 3600);
+    let id = suite.fund_cw20(SENDER, &token, 102, msg).unwrap();
+    assert_eq!(suite.cw20_balance(&token, SENDER), 898);
+    assert_eq!(suite.cw20_balance(&token, &contract), 102);
+
+    suite.confirm(RECEIVER, id, SECRET).unwrap();
+    assert_eq!(suite.cw20_balance(&token, RECEIVER), 100);
+    assert_eq!(suite.cw20_balance(&token, PLATFORM), 2);
+    assert_eq!(suite.cw20_balance(&token, &contract), 0);
+}
+
+#[test]
+fn batches_report_items_and_aggregate_payouts() {
+    let mut suite = native_suite(false);
+    let contract = suite.contract.clone();
+
+    let transfers = vec![
+        suite.transfer_msg(coin(100, "atom"), 3600),
+        TransferMsg {
+            allow_hashlock_reuse: true,
+            ..suite.transfer_msg(coin(100, "atom"), 3601)
+        },
+        // below the shortest lock
+        suite.transfer_msg(coin(100, "atom"), 1),
+    ];
+    let ids: Vec<_> = transfers.iter().map(|msg| msg.transfer_id()).collect();
+    let res = suite
+        .execute(
+            SENDER,
+            &ExecuteMsg::BatchFund {
+                transfers,
+                best_effort: true,
+            },
+            &coins(303, "atom"),
+        )
+        .unwrap();
+    let results: Vec<BatchItemResult> = from_json(res.data.unwrap()).unwrap();
+    assert_eq!(results[0].error, None);
+    assert_eq!(results[1].error, None);
+    assert!(results[2].error.is_some());
+    // the deposit of the skipped transfer is returned
+    assert_eq!(suite.balance(SENDER, "atom"), 798);
+    assert_eq!(suite.balance(&contract, "atom"), 202);
+
+    // an atomic batch fails as a whole
+    let err = suite
+        .execute(
+            SENDER,
+            &ExecuteMsg::BatchConfirm {
+                items: vec![(ids[0], SECRET), (ids[2], SECRET)],
+                best_effort: false,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::TransferNotExists
+    );
+    assert_eq!(suite.transfer(ids[0]).status, TransferStatus::Pending);
+
+    let res = suite
+        .execute(
+            RECEIVER,
+            &ExecuteMsg::BatchConfirm {
+                items: vec![(ids[0], SECRET), (ids[1], SECRET), (ids[2], SECRET)],
+                best_effort: true,
+            },
+            &[],
+        )
+        .unwrap();
+    let sends = res
+        .events
+        .iter()
+        .filter(|event| event.ty == "transfer")
+        .count();
+    assert_eq!(sends, 2);
+    assert_eq!(suite.balance(RECEIVER, "atom"), 200);
+    assert_eq!(suite.balance(PLATFORM, "atom"), 2);
+    assert_eq!(suite.balance(&contract, "atom"), 0);
+}
+
+#[test]
+fn overpayment_is_returned_or_rejected() {
+    for strict_payment in [false, true] {
+        let mut suite = Suite::new(
+            InstantiateMsg {
+                platform: PLATFORM.into(),
+                fees: vec![flat_fee("atom", 1)],
+                strict_payment,
+                ..Default::default()
+            },
+            &[(SENDER, vec![coin(1000, "atom"), coin(1000, "osmo")])],
+        );
+        let contract = suite.contract.clone();
+
+        let msg = suite.transfer_msg(coin(100, "atom"), 3600);
+        let overpaid = [coin(110, "atom"), coin(5, "osmo")];
+        if strict_payment {
+            let err = suite.fund(SENDER, msg.clone(), &overpaid).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::InexactPayment
+            );
+            suite.fund(SENDER, msg, &coins(101, "atom")).unwrap();
+        } else {
+            suite.fund(SENDER, msg, &overpaid).unwrap();
+        }
+        assert_eq!(suite.balance(SENDER, "atom"), 899);
+        assert_eq!(suite.balance(SENDER, "osmo"), 1000);
+        assert_eq!(suite.balance(&contract, "atom"), 101);
+        assert_eq!(suite.balance(&contract, "osmo"), 0);
+    }
+}
+
+#[test]
+fn relayer_collects_the_tip() {
+    let mut suite = native_suite(false);
+    let contract = suite.contract.clone();
+
+    let msg = TransferMsg {
+        tip: Some(coin(3, "atom")),
+        ..suite.transfer_msg(coin(100, "atom"), 3600)
+    };
+    let err = suite
+        .fund(SENDER, msg.clone(), &coins(101, "atom"))
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::InsufficientFundsSend
+    );
+    let id = suite.fund(SENDER, msg, &coins(104, "atom")).unwrap();
+    assert_eq!(suite.transfer(id).tip, Some(coin(3, "atom")));
+
+    suite.confirm("relayer", id, SECRET).unwrap();
+    assert_eq!(suite.balance(RECEIVER, "atom"), 100);
+    assert_eq!(suite.balance("relayer", "atom"), 3);
+    assert_eq!(suite.balance(PLATFORM, "atom"), 1);
+    assert_eq!(suite.balance(&contract, "atom"), 0);
+
+    // the tip goes back with the refund
+    let msg = TransferMsg {
+        hashlock: [7; 32],
+        tip: Some(coin(3, "atom")),
+        ..suite.transfer_msg(coin(100, "atom"), DEFAULT_MIN_TIMELOCK)
+    };
+    let id = suite.fund(SENDER, msg, &coins(104, "atom")).unwrap();
+    suite.advance_seconds(DEFAULT_MIN_TIMELOCK + 1);
+    suite.refund(SENDER, id).unwrap();
+    assert_eq!(suite.balance(SENDER, "atom"), 1000 - 104 - 1);
+    assert_eq!(suite.balance(&contract, "atom"), 0);
+}
+
+#[test]
+fn reused_hashlocks_need_an_opt_in() {
+    let mut suite = native_suite(false);
+
+    let msg = suite.transfer_msg(coin(100, "atom"), 3600);
+    let first = suite.fund(SENDER, msg, &coins(101, "atom")).unwrap();
+    assert_eq!(
+        suite.hashlock(HASHLOCK),
+        HashlockState {
+            transfers: 1,
+            revealed: false
+        }
+    );
+
+    let msg = suite.transfer_msg(coin(100, "atom"), 3601);
+    let err = suite
+        .fund(SENDER, msg.clone(), &coins(101, "atom"))
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::HashlockReused
+    );
+    let msg = TransferMsg {
+        allow_hashlock_reuse: true,
+        ..msg
+    };
+    suite.fund(SENDER, msg, &coins(101, "atom")).unwrap();
+    assert_eq!(suite.hashlock(HASHLOCK).transfers, 2);
+
+    // once revealed the hashlock locks nothing, even with the opt-in
+    suite.confirm(RECEIVER, first, SECRET).unwrap();
+    assert!(suite.hashlock(HASHLOCK).revealed);
+    let msg = TransferMsg {
+        allow_hashlock_reuse: true,
+        ..suite.transfer_msg(coin(100, "atom"), 3602)
+    };
+    let err = suite.fund(SENDER, msg, &coins(101, "atom")).unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::HashlockRevealed
+    );
+    assert_eq!(suite.hashlock([0; 32]), HashlockState::default());
+}
+
+#[test]
+fn funded_transfers_cannot_be_overwritten() {
+    let mut suite = native_suite(false);
+
+    let msg = suite.transfer_msg(coin(100, "atom"), 3600);
+    let id = suite
+        .fund(SENDER, msg.clone(), &coins(101, "atom"))
+        .unwrap();
+    let original = suite.transfer(id);
+
+    // the fields left out of the id cannot be swapped by funding a copy, with or without the
+    // opt-in
+    let copies = [
+        msg.clone(),
+        TransferMsg {
+            allow_hashlock_reuse: true,
+            referrer: Some("attacker".into()),
+            ..msg
+        },
+    ];
+    for copy in copies {
+        let err = suite.fund(SENDER, copy, &coins(101, "atom")).unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::TransferExists
+        );
+    }
+    assert_eq!(suite.transfer(id), original);
+    assert_eq!(suite.hashlock(HASHLOCK).transfers, 1);
+    assert_eq!(suite.balance(suite.contract.clone(), "atom"), 101);
+}
+
+#[test]
+fn skipped_batch_items_leave_no_trace() {
+    let mut suite = native_suite(false);
+    let contract = suite.contract.clone();
+    let msg = suite.transfer_msg(coin(100, "atom"), 3600);
+    suite.fund(SENDER, msg, &coins(101, "atom")).unwrap();
+    let solvency = |suite: &Suite| -> Vec<Solvency> {
+        suite
+            .app
+            .wrap()
+            .query_wasm_smart(&contract, &QueryMsg::Solvency {})
+            .unwrap()
+    };
+    let before = solvency(&suite);
+
+    let transfers = vec![
+        // reuses the hashlock without the opt-in
+        suite.transfer_msg(coin(100, "atom"), 3601),
+        // fails on the receiver address, after the limits and the hashlock were checked
+        TransferMsg {
+            receiver: "".into(),
+            hashlock: [8; 32],
+            ..suite.transfer_msg(coin(100, "atom"), 3600)
+        },
+    ];
+    for _ in 0..3 {
+        let res = suite
+            .execute(
+                SENDER,
+                &ExecuteMsg::BatchFund {
+                    transfers: transfers.clone(),
+                    best_effort: true,
+                },
+                &coins(202, "atom"),
+            )
+            .unwrap();
+        let results: Vec<BatchItemResult> = from_json(res.data.unwrap()).unwrap();
+        assert!(results.iter().all(|item| item.error.is_some()));
+    }
+
+    assert_eq!(solvency(&suite), before);
+    assert_eq!(before[0].totals.locked, Uint128::new(100));
+    assert_eq!(before[0].shortfall, Uint128::zero());
+    assert_eq!(suite.hashlock(HASHLOCK).transfers, 1);
+    assert_eq!(suite.hashlock([8; 32]), HashlockState::default());
+}
+
+#[test]
+fn failed_forward_is_claimable() {
+    let mut suite = native_suite(false);
+    let contract = suite.contract.clone();
+
+    let msg = TransferMsg {
+        forward: Some(Forward {
+            channel: "channel-0".into(),
+            remote_address: "osmo1receiver".into(),
+            timeout: 600,
+        }),
+        ..suite.transfer_msg(coin(100, "atom"), 3600)
+    };
+    let id = suite.fund(SENDER, msg, &coins(101, "atom")).unwrap();
+
+    // the test chain has no ICS-20 module, so the transfer fails but the confirm goes through
+    suite.confirm(RECEIVER, id, SECRET).unwrap();
+    assert_eq!(suite.transfer(id).status, TransferStatus::Confirmed);
+    assert_eq!(suite.balance(PLATFORM, "atom"), 1);
+    assert_eq!(suite.claimable(RECEIVER), coins(100, "atom"));
+
+    suite.execute(RECEIVER, &ExecuteMsg::Claim {}, &[]).unwrap();
+    assert_eq!(suite.balance(RECEIVER, "atom"), 100);
+    assert_eq!(suite.balance(&contract, "atom"), 0);
+    let err = suite
+        .execute(RECEIVER, &ExecuteMsg::Claim {}, &[])
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::NothingToClaim
+    );
+}
+
+#[test]
+fn platform_fee_is_split() {
+    let mut suite = Suite::new(
+        InstantiateMsg {
+            platform: PLATFORM.into(),
+            fees: vec![flat_fee("atom", 10)],
+            fee_recipients: vec![
+                FeeRecipient {
+                    address: "integrator".into(),
+                    weight: 2,
+                },
+                FeeRecipient {
+                    address: "partner".into(),
+                    weight: 1,
+                },
+            ],
+            referrer_share_bps: Some(2_000),
+            ..Default::default()
+        },
+        &[(SENDER, coins(1000, "atom"))],
+    );
+
+    let msg = TransferMsg {
+        referrer: Some("referrer".into()),
+        ..suite.transfer_msg(coin(100, "atom"), 3600)
+    };
+    let id = suite.fund(SENDER, msg, &coins(110, "atom")).unwrap();
+    let res = suite.confirm(RECEIVER, id, SECRET).unwrap();
+    let sends = res
+        .events
+        .iter()
+        .filter(|event| event.ty == "transfer")
+        .count();
+    assert_eq!(sends, 5);
+    assert_eq!(suite.balance(RECEIVER, "atom"), 100);
+    // 20% to the referrer, the rest by weight, the platform keeps the rounding
+    assert_eq!(suite.balance("referrer", "atom"), 2);
+    assert_eq!(suite.balance("integrator", "atom"), 5);
+    assert_eq!(suite.balance("partner", "atom"), 2);
+    assert_eq!(suite.balance(PLATFORM, "atom"), 1);
+
+    let update = ExecuteMsg::UpdateConfig {
+        platform: None,
+        fees: None,
+        refund_fee: None,
+        min_timelock: None,
+        max_timelock: None,
+        min_height_lock: None,
+        max_height_lock: None,
+        cleanup_retention: None,
+        cleanup_bounty_bps: None,
+        strict_payment: None,
+        restrict_assets: None,
+        fee_recipients: Some(vec![FeeRecipient {
+            address: "integrator".into(),
+            weight: 0,
+        }]),
+        referrer_share_bps: None,
+    };
+    let err = suite.execute(OWNER, &update, &[]).unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::InvalidFeeSplit
+    );
+}
+
+#[test]
+fn exclusive_window_holds_off_relayers() {
+    let mut suite = native_suite(false);
+
+    let exclusive = |suite: &Suite, lock: u64| TransferMsg {
+        exclusive_window: Some(600),
+        delegate: Some("delegate".into()),
+        allow_hashlock_reuse: true,
+        ..suite.transfer_msg(coin(100, "atom"), lock)
+    };
+    let msg = exclusive(&suite, 3600);
+    let first = suite.fund(SENDER, msg, &coins(101, "atom")).unwrap();
+    let msg = exclusive(&suite, 3601);
+    let second = suite.fund(SENDER, msg, &coins(101, "atom")).unwrap();
+    let msg = TransferMsg {
+        exclusive_window: Some(u64::MAX),
+        ..exclusive(&suite, 3602)
+    };
+    let endless = suite.fund(SENDER, msg, &coins(101, "atom")).unwrap();
+    assert_eq!(suite.transfer(endless).exclusive_until, Some(u64::MAX));
+    let until = suite.transfer(first).exclusive_until.unwrap();
+    assert_eq!(until, suite.transfer_msg(coin(100, "atom"), 600).timelock);
+
+    let err = suite.confirm("relayer", first, SECRET).unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::ExclusiveWindow { until }
+    );
+    suite.confirm("delegate", first, SECRET).unwrap();
+    assert_eq!(suite.balance(RECEIVER, "atom"), 100);
+
+    // anyone can confirm once the window is over
+    assert!(suite.confirm("relayer", second, SECRET).is_err());
+    suite.advance_seconds(600);
+    suite.confirm("relayer", second, SECRET).unwrap();
+    assert_eq!(suite.balance(RECEIVER, "atom"), 200);
+
+    // a window past the end of time still leaves the receiver to pick who confirms
+    assert!(suite.confirm("relayer", endless, SECRET).is_err());
+    let set_delegate = ExecuteMsg::SetDelegate {
+        id: endless,
+        delegate: Some("relayer".into()),
+    };
+    let err = suite.execute(SENDER, &set_delegate, &[]).unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::Unauthorized
+    );
+    suite.execute(RECEIVER, &set_delegate, &[]).unwrap();
+    suite.confirm("relayer", endless, SECRET).unwrap();
+    assert_eq!(suite.balance(RECEIVER, "atom"), 300);
+}
+
+#[test]
+fn only_the_surplus_is_swept() {
+    let mut suite = native_suite(false);
+    let contract = suite.contract.clone();
+
+    let msg = suite.transfer_msg(coin(100, "atom"), 3600);
+    let id = suite.fund(SENDER, msg, &coins(101, "atom")).unwrap();
+    // a stray deposit
+    suite
+        .app
+        .send_tokens(
+            Addr::unchecked(SENDER),
+            contract.clone(),
+            &coins(50, "atom"),
+        )
+        .unwrap();
+
+    let solvency: Vec<Solvency> = suite
+        .app
+        .wrap()
+        .query_wasm_smart(&contract, &QueryMsg::Solvency {})
+        .unwrap();
+    assert_eq!(solvency.len(), 1);
+    assert_eq!(solvency[0].totals.locked, Uint128::new(100));
+    assert_eq!(solvency[0].totals.reserved, Uint128::new(1));
+    assert_eq!(solvency[0].balance, Uint128::new(151));
+    assert_eq!(solvency[0].surplus, Uint128::new(50));
+    assert_eq!(solvency[0].shortfall, Uint128::zero());
+
+    let sweep = |amount: Option<u128>| ExecuteMsg::Sweep {
+        denom: "atom".into(),
+        amount: amount.map(Uint128::new),
+        recipient: Some("treasury".into()),
+    };
+    let err = suite.execute(SENDER, &sweep(None), &[]).unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::Unauthorized
+    );
+    let err = suite.execute(OWNER, &sweep(Some(51)), &[]).unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::SweepExceedsSurplus {
+            surplus: Uint128::new(50)
+        }
+    );
+    suite.execute(OWNER, &sweep(None), &[]).unwrap();
+    assert_eq!(suite.balance("treasury", "atom"), 50);
+    assert_eq!(suite.balance(&contract, "atom"), 101);
+
+    suite.confirm(RECEIVER, id, SECRET).unwrap();
+    let solvency: Vec<Solvency> = suite
+        .app
+        .wrap()
+        .query_wasm_smart(&contract, &QueryMsg::Solvency {})
+        .unwrap();
+    assert_eq!(solvency[0].totals.locked, Uint128::zero());
+    assert_eq!(solvency[0].totals.confirmed, Uint128::new(100));
+    assert_eq!(solvency[0].totals.reserved, Uint128::zero());
+    assert_eq!(solvency[0].balance, Uint128::zero());
+}
+
+#[test]
+fn premium_is_forfeited_or_returned() {
+    let mut suite = Suite::new(
+        InstantiateMsg {
+            platform: PLATFORM.into(),
+            fees: vec![flat_fee("atom", 1)],
+            ..Default::default()
+        },
+        &[
+            (SENDER, coins(1000, "atom")),
+            (RECEIVER, coins(100, "atom")),
+        ],
+    );
+    let with_premium = |suite: &Suite, lock: u64| TransferMsg {
+        premium: Some(PremiumTerms {
+            coin: coin(10, "atom"),
+            deposit_window: 600,
+        }),
+        allow_hashlock_reuse: true,
+        ..suite.transfer_msg(coin(100, "atom"), lock)
+    };
+    let deposit = |id| ExecuteMsg::DepositPremium { id };
+
+    // a deposit window past the longest timelock is rejected
+    let msg = TransferMsg {
+        premium: Some(PremiumTerms {
+            coin: coin(10, "atom"),
+            deposit_window: u64::MAX,
+        }),
+        ..with_premium(&suite, 3600)
+    };
+    let err = suite.fund(SENDER, msg, &coins(101, "atom")).unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::InvalidPremium
+    );
+
+    // expired unconfirmed, the deposit goes to the sender
+    let msg = with_premium(&suite, 3600);
+    let expired = suite.fund(SENDER, msg, &coins(101, "atom")).unwrap();
+    let msg = with_premium(&suite, 3601);
+    let confirmed = suite.fund(SENDER, msg, &coins(101, "atom")).unwrap();
+    // only the receiver is held to the swap
+    let err = suite
+        .execute(SENDER, &deposit(expired), &coins(10, "atom"))
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::Unauthorized
+    );
+    suite
+        .execute(RECEIVER, &deposit(expired), &coins(10, "atom"))
+        .unwrap();
+    suite
+        .execute(RECEIVER, &deposit(confirmed), &coins(10, "atom"))
+        .unwrap();
+    let err = suite
+        .execute(RECEIVER, &deposit(expired), &coins(10, "atom"))
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::NoPremiumDue
+    );
+    assert_eq!(suite.balance(RECEIVER, "atom"), 80);
+
+    // confirmed, the deposit goes back to the counterparty
+    suite.confirm(RECEIVER, confirmed, SECRET).unwrap();
+    assert_eq!(suite.balance(RECEIVER, "atom"), 190);
+    assert_eq!(
+        suite.premium(confirmed).unwrap().status,
+        PremiumStatus::Returned
+    );
+    suite.advance_seconds(3601);
+    suite.refund("relayer", expired).unwrap();
+    assert_eq!(suite.balance(SENDER, "atom"), 1000 - 202 + 110);
+    assert_eq!(
+        suite.premium(expired).unwrap().status,
+        PremiumStatus::Forfeited
+    );
+
+    // left unpaid past its window, the sender refunds without waiting for the timelock
+    let msg = TransferMsg {
+        hashlock: [9; 32],
+        ..with_premium(&suite, 3600)
+    };
+    let unpaid = suite.fund(SENDER, msg, &coins(101, "atom")).unwrap();
+    assert_eq!(
+        suite
+            .refund(SENDER, unpaid)
+            .unwrap_err()
+            .downcast::<ContractError>()
+            .unwrap(),
+        ContractError::TransferLocked
+    );
+    suite.advance_seconds(601);
+    let due = suite.premium(unpaid).unwrap().due;
+    let err = suite
+        .execute(RECEIVER, &deposit(unpaid), &coins(10, "atom"))
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::PremiumOverdue { due }
+    );
+    suite.refund(SENDER, unpaid).unwrap();
+    assert_eq!(suite.balance(SENDER, "atom"), 1000 - 202 + 110 - 1);
+    assert_eq!(suite.premium(unpaid).unwrap().status, PremiumStatus::Lapsed);
+    assert_eq!(suite.balance(suite.contract.clone(), "atom"), 0);
+}
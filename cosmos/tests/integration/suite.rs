@@ -0,0 +1,245 @@
+//! A multi-test harness running the contract with a real bank module and CW20 tokens.
+//!
+//! `Suite::new` deploys the contract, the test cases then drive it through the helpers and
+//! assert on the balances kept by the bank module or the token contracts.
+use anyhow::Result as AnyResult;
+use cosmwasm_std::{to_json_binary, Addr, Coin, Empty, Uint128};
+use cw20::{BalanceResponse, Cw20Coin, Cw20ExecuteMsg, Cw20QueryMsg};
+use cw_multi_test::{App, AppResponse, Contract, ContractWrapper, Executor};
+use utils::HashLock;
+
+use cosmos_atomic_swap::{
+    ExecuteMsg, HashlockState, InstantiateMsg, Premium, QueryMsg, ReceiveMsg, TransferId,
+    TransferMsg, TransferRecord,
+};
+
+pub const OWNER: &str = "owner";
+pub const PLATFORM: &str = "platform";
+pub const SENDER: &str = "sender";
+pub const RECEIVER: &str = "receiver";
+
+/// The secret opening `HASHLOCK`
+pub const SECRET: [u8; 32] = *b"ssssssssssssssssssssssssssssssss";
+pub const HASHLOCK: [u8; 32] = [
+    165, 152, 132, 76, 216, 153, 182, 114, 45, 89, 20, 251, 170, 95, 204, 77, 214, 166, 43, 58,
+    171, 243, 206, 181, 109, 46, 63, 177, 197, 13, 234, 154,
+];
+
+pub fn atomic_swap_contract() -> Box<dyn Contract<Empty>> {
+    Box::new(
+        ContractWrapper::new(
+            cosmos_atomic_swap::execute,
+            cosmos_atomic_swap::instantiate,
+            cosmos_atomic_swap::query,
+        )
+        .with_migrate(cosmos_atomic_swap::migrate)
+        .with_sudo(cosmos_atomic_swap::sudo)
+        .with_reply(cosmos_atomic_swap::forward::reply),
+    )
+}
+
+pub fn cw20_contract() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(
+        cw20_base::contract::execute,
+        cw20_base::contract::instantiate,
+        cw20_base::contract::query,
+    ))
+}
+
+pub struct Suite {
+    pub app: App,
+    pub contract: Addr,
+}
+
+impl Suite {
+    /// deploys the contract with `msg`, every account in `balances` starts with its coins
+    pub fn new(msg: InstantiateMsg, balances: &[(&str, Vec<Coin>)]) -> Self {
+        let mut app = App::new(|router, _api, storage| {
+            for (addr, coins) in balances {
+                router
+                    .bank
+                    .init_balance(storage, &Addr::unchecked(*addr), coins.clone())
+                    .unwrap();
+            }
+        });
+        let code_id = app.store_code(atomic_swap_contract());
+        let contract = app
+            .instantiate_contract(
+                code_id,
+                Addr::unchecked(OWNER),
+                &msg,
+                &[],
+                "atomic swap",
+                Some(OWNER.into()),
+            )
+            .unwrap();
+        Self { app, contract }
+    }
+
+    /// a transfer from `SENDER` to `RECEIVER` locked with `HASHLOCK` for `lock` seconds
+    pub fn transfer_msg(&self, coin: Coin, lock: u64) -> TransferMsg {
+        TransferMsg {
+            sender: SENDER.into(),
+            receiver: RECEIVER.into(),
+            coin,
+            hashlock: HASHLOCK,
+            timelock: self.app.block_info().time.seconds() + lock,
+            ..Default::default()
+        }
+    }
+
+    pub fn execute(
+        &mut self,
+        caller: &str,
+        msg: &ExecuteMsg,
+        funds: &[Coin],
+    ) -> AnyResult<AppResponse> {
+        self.app
+            .execute_contract(Addr::unchecked(caller), self.contract.clone(), msg, funds)
+    }
+
+    /// funds with native coins and returns the transfer id
+    pub fn fund(
+        &mut self,
+        caller: &str,
+        msg: TransferMsg,
+        funds: &[Coin],
+    ) -> AnyResult<TransferId> {
+        let res = self.execute(caller, &ExecuteMsg::Fund(msg), funds)?;
+        Ok(transfer_id(&res))
+    }
+
+    pub fn confirm(
+        &mut self,
+        caller: &str,
+        id: TransferId,
+        secret: [u8; 32],
+    ) -> AnyResult<AppResponse> {
+        self.execute(caller, &ExecuteMsg::ConfirmById { id, secret }, &[])
+    }
+
+    pub fn refund(&mut self, caller: &str, id: TransferId) -> AnyResult<AppResponse> {
+        self.execute(caller, &ExecuteMsg::RefundById { id }, &[])
+    }
+
+    pub fn transfer(&self, id: TransferId) -> TransferRecord {
+        self.app
+            .wrap()
+            .query_wasm_smart(&self.contract, &QueryMsg::TransferById { id })
+            .unwrap()
+    }
+
+    pub fn hashlock(&self, hashlock: HashLock) -> HashlockState {
+        self.app
+            .wrap()
+            .query_wasm_smart(&self.contract, &QueryMsg::Hashlock { hashlock })
+            .unwrap()
+    }
+
+    pub fn claimable(&self, addr: impl Into<String>) -> Vec<Coin> {
+        let address = addr.into();
+        self.app
+            .wrap()
+            .query_wasm_smart(&self.contract, &QueryMsg::Claimable { address })
+            .unwrap()
+    }
+
+    pub fn premium(&self, id: TransferId) -> Option<Premium> {
+        self.app
+            .wrap()
+            .query_wasm_smart(&self.contract, &QueryMsg::Premium { id })
+            .unwrap()
+    }
+
+    pub fn balance(&self, addr: impl Into<String>, denom: &str) -> u128 {
+        self.app
+            .wrap()
+            .query_balance(addr, denom)
+            .unwrap()
+            .amount
+            .u128()
+    }
+
+    /// moves the chain forward, assuming 5 seconds a block
+    pub fn advance_seconds(&mut self, seconds: u64) {
+        self.app.update_block(|block| {
+            block.time = block.time.plus_seconds(seconds);
+            block.height += seconds / 5;
+        });
+    }
+
+    /// deploys a CW20 token with the initial balances
+    pub fn create_cw20(&mut self, symbol: &str, balances: &[(&str, u128)]) -> Addr {
+        let code_id = self.app.store_code(cw20_contract());
+        self.app
+            .instantiate_contract(
+                code_id,
+                Addr::unchecked(OWNER),
+                &cw20_base::msg::InstantiateMsg {
+                    name: symbol.into(),
+                    symbol: symbol.into(),
+                    decimals: 6,
+                    initial_balances: balances
+                        .iter()
+                        .map(|(address, amount)| Cw20Coin {
+                            address: address.to_string(),
+                            amount: Uint128::new(*amount),
+                        })
+                        .collect(),
+                    mint: None,
+                    marketing: None,
+                },
+                &[],
+                symbol,
+                None,
+            )
+            .unwrap()
+    }
+
+    /// funds by sending `amount` of the token along with the embedded fund message
+    pub fn fund_cw20(
+        &mut self,
+        caller: &str,
+        token: &Addr,
+        amount: u128,
+        msg: TransferMsg,
+    ) -> AnyResult<TransferId> {
+        // the data set by the contract is not forwarded by the token contract
+        let id = msg.transfer_id();
+        self.app.execute_contract(
+            Addr::unchecked(caller),
+            token.clone(),
+            &Cw20ExecuteMsg::Send {
+                contract: self.contract.to_string(),
+                amount: Uint128::new(amount),
+                msg: to_json_binary(&ReceiveMsg::Fund(msg))?,
+            },
+            &[],
+        )?;
+        Ok(id)
+    }
+
+    pub fn cw20_balance(&self, token: &Addr, addr: impl Into<String>) -> u128 {
+        let res: BalanceResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(
+                token,
+                &Cw20QueryMsg::Balance {
+                    address: addr.into(),
+                },
+            )
+            .unwrap();
+        res.balance.u128()
+    }
+}
+
+/// the transfer id is the data of the fund response
+fn transfer_id(res: &AppResponse) -> TransferId {
+    res.data
+        .as_ref()
+        .expect("fund returns the transfer id")
+        .to_vec()
+        .try_into()
+        .expect("the transfer id has 32 bytes")
+}